@@ -12,7 +12,13 @@
 //! use.
 
 use const_gen::*;
-use std::{env, fs, fs::File, io::Read, path::Path};
+use std::{
+    env,
+    fs,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 use xz2::read::XzEncoder;
 
 fn main() {
@@ -20,6 +26,14 @@ fn main() {
     println!("cargo:rerun-if-changed=vial.json");
     generate_vial_config();
 
+    // Put `memory.x` in our output directory and ensure it's on the linker
+    // search path. It leaves the settings pages at the end of flash out of
+    // the program.
+    let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out.join("memory.x"), include_bytes!("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory.x");
+
     // Specify linker arguments.
 
     // `--nmagic` is required if memory section addresses are not aligned to
@@ -29,6 +43,8 @@ fn main() {
 
     // Set the linker script to the one provided by cortex-m-rt.
     println!("cargo:rustc-link-arg=-Tlink.x");
+    // defmt's symbol table for the warnings sent over RTT.
+    println!("cargo:rustc-link-arg=-Tdefmt.x");
 }
 
 fn generate_vial_config() {
@@ -0,0 +1,15 @@
+/* STM32L432KB: 128K flash in 2K pages, 64K RAM (SRAM1 and SRAM2 back to back).
+ *
+ * The last 5 flash pages hold settings and are not part of the program, see
+ * the flash layout in src/main.rs, which checks it against PROGRAM_FLASH_SIZE.
+ * From the top down:
+ *   2 pages  rmk keymap storage
+ *   2 pages  lighting themes and custom effect program
+ *   1 page   matrix scan settings
+ */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 128K - 5 * 2K
+  RAM   : ORIGIN = 0x20000000, LENGTH = 64K
+}
+
@@ -0,0 +1,121 @@
+#!/usr/bin/env python3
+"""Flash a notification on the keyboard backlight over raw HID.
+
+Examples:
+
+    # Whole board blinks red for 5 seconds
+    scripts/notify.py --color 0 255 255 --pattern blink --seconds 5
+
+    # Pulse Esc and F1 (LED 0 and 1) green, against the built-in mock device
+    scripts/notify.py --color 85 255 255 --pattern pulse --leds 0 1 --mock
+
+Talking to real hardware needs the `hid` package (hidapi bindings).
+
+The firmware does not receive these reports yet: rmk 0.8 keeps raw HID to its
+Vial service, see the readme. Until then only `--mock` does anything.
+"""
+
+import argparse
+import sys
+
+VID = 0x3434
+PID = 0x0611
+RAW_HID_USAGE_PAGE = 0xFF60
+
+REPORT_LENGTH = 32
+CUSTOM_SET_VALUE = 0x07
+UNHANDLED = 0xFF
+CHANNEL_LIGHTING = 0x20
+LIGHTING_NOTIFY = 0x07
+
+LED_COUNT = 83
+MASK_LEN = (LED_COUNT + 7) // 8
+PATTERNS = {"solid": 0, "blink": 1, "pulse": 2}
+
+
+def build_report(hsv, pattern, seconds, leds):
+    duration = round(seconds * 10)
+    if not 0 <= duration <= 0xFFFF:
+        raise ValueError("duration out of range")
+
+    mask = bytearray(MASK_LEN)
+    for led in leds:
+        if not 0 <= led < LED_COUNT:
+            raise ValueError(f"LED index {led} out of range")
+        mask[led // 8] |= 1 << (led % 8)
+
+    data = bytes(hsv) + bytes([PATTERNS[pattern]]) + duration.to_bytes(2, "little") + bytes(mask)
+    report = bytes([CUSTOM_SET_VALUE, CHANNEL_LIGHTING, LIGHTING_NOTIFY]) + data
+    return report.ljust(REPORT_LENGTH, b"\0")
+
+
+class MockDevice:
+    """Decodes notification requests the way the firmware does."""
+
+    def write(self, report):
+        assert len(report) == REPORT_LENGTH, "raw HID reports are 32 bytes"
+        self.reply = bytearray(report)
+
+        command, channel, value = report[0], report[1], report[2]
+        if (command, channel, value) != (CUSTOM_SET_VALUE, CHANNEL_LIGHTING, LIGHTING_NOTIFY):
+            self.reply[0] = UNHANDLED
+            return
+
+        h, s, v, pattern = report[3:7]
+        if pattern not in PATTERNS.values():
+            self.reply[0] = UNHANDLED
+            return
+
+        duration_ms = int.from_bytes(report[7:9], "little") * 100
+        mask = report[9 : 9 + MASK_LEN]
+        leds = [i for i in range(LED_COUNT) if mask[i // 8] & (1 << (i % 8))] or list(range(LED_COUNT))
+        name = next(k for k, p in PATTERNS.items() if p == pattern)
+        print(f"mock: {name} hsv=({h}, {s}, {v}) for {duration_ms} ms on {len(leds)} LED(s): {leds}")
+
+    def read(self):
+        return bytes(self.reply)
+
+
+class HidDevice:
+    def __init__(self):
+        import hid
+
+        path = next(
+            (d["path"] for d in hid.enumerate(VID, PID) if d["usage_page"] == RAW_HID_USAGE_PAGE),
+            None,
+        )
+        if path is None:
+            sys.exit("keyboard raw HID interface not found")
+        self.dev = hid.device()
+        self.dev.open_path(path)
+
+    def write(self, report):
+        # Leading zero is the HID report id.
+        self.dev.write(b"\0" + report)
+
+    def read(self):
+        return bytes(self.dev.read(REPORT_LENGTH, 1000))
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("--color", nargs=3, type=int, metavar=("H", "S", "V"), default=[0, 255, 255])
+    parser.add_argument("--pattern", choices=PATTERNS, default="blink")
+    parser.add_argument("--seconds", type=float, default=3.0, help="0 clears a running notification")
+    parser.add_argument("--leds", nargs="*", type=int, default=[], help="LED indices, whole board if omitted")
+    parser.add_argument("--mock", action="store_true", help="send to an in-process mock device")
+    args = parser.parse_args()
+
+    if any(not 0 <= c <= 255 for c in args.color):
+        parser.error("color components must be 0..=255")
+
+    report = build_report(args.color, args.pattern, args.seconds, args.leds)
+    dev = MockDevice() if args.mock else HidDevice()
+    dev.write(report)
+    reply = dev.read()
+    if not reply or reply[0] == UNHANDLED:
+        sys.exit("notification rejected by the keyboard")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,92 @@
+#!/usr/bin/env python3
+"""Upload a compiled custom lighting effect over raw HID.
+
+Examples:
+
+    # Compile and upload, then select it with the Custom effect (10)
+    cargo make effectc effects/ripple.fx -o ../ripple.bin
+    scripts/upload_effect.py ripple.bin
+
+    # Dry run against the built-in mock device
+    scripts/upload_effect.py ripple.bin --mock
+
+Talking to real hardware needs the `hid` package (hidapi bindings).
+
+The firmware does not receive these reports yet: rmk 0.8 keeps raw HID to its
+Vial service, see the readme. Until then only `--mock` does anything.
+"""
+
+import argparse
+import sys
+
+from notify import CHANNEL_LIGHTING, CUSTOM_SET_VALUE, REPORT_LENGTH, UNHANDLED, HidDevice
+
+LIGHTING_PROGRAM_DATA = 0x09
+LIGHTING_PROGRAM_COMMIT = 0x0A
+
+MAX_PROGRAM_LEN = 512
+CHUNK_LEN = REPORT_LENGTH - 6
+
+
+def build_reports(code):
+    if len(code) > MAX_PROGRAM_LEN:
+        raise ValueError(f"program is {len(code)} bytes, the limit is {MAX_PROGRAM_LEN}")
+
+    for offset in range(0, len(code), CHUNK_LEN):
+        chunk = code[offset : offset + CHUNK_LEN]
+        header = bytes([CUSTOM_SET_VALUE, CHANNEL_LIGHTING, LIGHTING_PROGRAM_DATA])
+        report = header + offset.to_bytes(2, "little") + bytes([len(chunk)]) + chunk
+        yield report.ljust(REPORT_LENGTH, b"\0")
+
+    commit = bytes([CUSTOM_SET_VALUE, CHANNEL_LIGHTING, LIGHTING_PROGRAM_COMMIT]) + len(code).to_bytes(2, "little")
+    yield commit.ljust(REPORT_LENGTH, b"\0")
+
+
+class MockDevice:
+    """Reassembles the upload the way the firmware does."""
+
+    def __init__(self):
+        self.upload = bytearray(MAX_PROGRAM_LEN)
+
+    def write(self, report):
+        assert len(report) == REPORT_LENGTH, "raw HID reports are 32 bytes"
+        self.reply = bytearray(report)
+
+        command, channel, value = report[0], report[1], report[2]
+        if (command, channel) != (CUSTOM_SET_VALUE, CHANNEL_LIGHTING):
+            self.reply[0] = UNHANDLED
+        elif value == LIGHTING_PROGRAM_DATA:
+            offset, n = int.from_bytes(report[3:5], "little"), min(report[5], CHUNK_LEN)
+            if offset + n > MAX_PROGRAM_LEN:
+                self.reply[0] = UNHANDLED
+            else:
+                self.upload[offset : offset + n] = report[6 : 6 + n]
+        elif value == LIGHTING_PROGRAM_COMMIT:
+            n = int.from_bytes(report[3:5], "little")
+            print(f"mock: program of {n} bytes: {self.upload[:n].hex()}")
+        else:
+            self.reply[0] = UNHANDLED
+
+    def read(self):
+        return bytes(self.reply)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    parser.add_argument("program", help="bytecode written by effectc")
+    parser.add_argument("--mock", action="store_true", help="send to an in-process mock device")
+    args = parser.parse_args()
+
+    with open(args.program, "rb") as f:
+        code = f.read()
+
+    dev = MockDevice() if args.mock else HidDevice()
+    for report in build_reports(code):
+        dev.write(report)
+        reply = dev.read()
+        if not reply or reply[0] == UNHANDLED:
+            sys.exit("upload rejected by the keyboard")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,15 +1,15 @@
-use crate::ckled2001::registers::*;
-use embassy_stm32::{i2c, i2c::I2c, mode::Async};
+use crate::ckled2001::{led_address::CkLed, registers::*};
+use embassy_time::{Duration, Instant};
+use embedded_hal_async::i2c::I2c;
 
-pub const DEFAULT_CURRENT_TUNE: [u8; LED_CURRENT_TUNE_LENGTH] = [0xFF; LED_CURRENT_TUNE_LENGTH];
+/// Function page registers read back to detect a driver reset, with the
+/// values written during init.
+const CONFIG_READBACK: [(u8, u8); 2] = [(CONFIGURATION_REG, MSKSW_NORMAL_MODE), (PDU_REG, MSKSET_CA_CB_CHANNEL)];
 
-#[derive(Copy, Clone)]
-pub struct CkLed {
-    pub driver: u8,
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-}
+/// Largest register block written at once: a whole PWM page.
+const MAX_BLOCK: usize = LED_PWM_LENGTH;
+
+pub const DEFAULT_CURRENT_TUNE: [u8; LED_CURRENT_TUNE_LENGTH] = [0xFF; LED_CURRENT_TUNE_LENGTH];
 
 #[derive(Debug, Copy, Clone)]
 pub enum CkledError {
@@ -17,31 +17,41 @@ pub enum CkledError {
     BlockTooLarge,
 }
 
-pub struct Ckled2001<'d, const DRIVER_COUNT: usize> {
-    i2c: I2c<'d, Async, i2c::Master>,
+pub struct Ckled2001<I: I2c, const DRIVER_COUNT: usize> {
+    i2c: I,
     addrs: [u8; DRIVER_COUNT],
     leds: &'static [CkLed],
 
-    pwm: [[u8; LED_PWM_LENGTH]; DRIVER_COUNT],
+    /// Frame being rendered; never sent to the drivers directly.
+    back: [[u8; LED_PWM_LENGTH]; DRIVER_COUNT],
+    /// Last presented frame, the only source for PWM writes.
+    front: [[u8; LED_PWM_LENGTH]; DRIVER_COUNT],
     pwm_dirty: [bool; DRIVER_COUNT],
 
     led_ctrl: [[u8; LED_CONTROL_ON_OFF_LENGTH]; DRIVER_COUNT],
     led_ctrl_dirty: [bool; DRIVER_COUNT],
 
     global_brightness: u8,
+
+    /// Duration of the last flush that wrote anything, and the longest one.
+    last_flush: Duration,
+    max_flush: Duration,
 }
 
-impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
-    pub fn new(i2c: I2c<'d, Async, i2c::Master>, addrs: [u8; DRIVER_COUNT], leds: &'static [CkLed]) -> Self {
+impl<I: I2c, const DRIVER_COUNT: usize> Ckled2001<I, DRIVER_COUNT> {
+    pub fn new(i2c: I, addrs: [u8; DRIVER_COUNT], leds: &'static [CkLed]) -> Self {
         Self {
             i2c,
             addrs,
             leds,
-            pwm: [[0; LED_PWM_LENGTH]; DRIVER_COUNT],
+            back: [[0; LED_PWM_LENGTH]; DRIVER_COUNT],
+            front: [[0; LED_PWM_LENGTH]; DRIVER_COUNT],
             pwm_dirty: [false; DRIVER_COUNT],
             led_ctrl: [[0; LED_CONTROL_ON_OFF_LENGTH]; DRIVER_COUNT],
             led_ctrl_dirty: [false; DRIVER_COUNT],
             global_brightness: 255,
+            last_flush: Duration::from_ticks(0),
+            max_flush: Duration::from_ticks(0),
         }
     }
 
@@ -63,11 +73,9 @@ impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
             return;
         }
 
-        self.pwm[d][led.r as usize] = r;
-        self.pwm[d][led.g as usize] = g;
-        self.pwm[d][led.b as usize] = b;
-
-        self.pwm_dirty[d] = true;
+        self.back[d][led.r as usize] = r;
+        self.back[d][led.g as usize] = g;
+        self.back[d][led.b as usize] = b;
     }
 
     #[inline]
@@ -89,6 +97,13 @@ impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
         self.i2c.write(addr7, bytes).await.map_err(|_| CkledError::I2c)
     }
 
+    #[inline]
+    async fn read_reg(&mut self, addr7: u8, reg: u8) -> Result<u8, CkledError> {
+        let mut buf = [0u8; 1];
+        self.i2c.write_read(addr7, &[reg], &mut buf).await.map_err(|_| CkledError::I2c)?;
+        Ok(buf[0])
+    }
+
     #[inline]
     async fn write_reg(&mut self, addr7: u8, reg: u8, data: u8) -> Result<(), CkledError> {
         self.write_bytes(addr7, &[reg, data]).await
@@ -99,84 +114,137 @@ impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
         self.write_reg(addr7, CONFIGURE_CMD_PAGE, page).await
     }
 
+    /// Write consecutive registers in a single transaction.
     async fn write_block(&mut self, addr7: u8, start_reg: u8, data: &[u8]) -> Result<(), CkledError> {
-        if data.len() > 64 {
+        if data.len() > MAX_BLOCK {
             return Err(CkledError::BlockTooLarge);
         }
 
-        let mut buf = [0u8; 65];
+        let mut buf = [0u8; 1 + MAX_BLOCK];
         buf[0] = start_reg;
         buf[1..1 + data.len()].copy_from_slice(data);
 
         self.write_bytes(addr7, &buf[..1 + data.len()]).await
     }
 
+    /// Write `len` consecutive registers with the same value in a single
+    /// transaction.
     async fn write_repeat(&mut self, addr7: u8, start_reg: u8, value: u8, len: usize) -> Result<(), CkledError> {
-        let mut tmp = [0u8; 64];
-        tmp.fill(value);
-
-        let mut offset = 0usize;
-        while offset < len {
-            let n = (len - offset).min(64);
-            self.write_block(addr7, start_reg.wrapping_add(offset as u8), &tmp[..n]).await?;
-            offset += n;
+        if len > MAX_BLOCK {
+            return Err(CkledError::BlockTooLarge);
         }
-        Ok(())
+
+        let mut buf = [value; 1 + MAX_BLOCK];
+        buf[0] = start_reg;
+
+        self.write_bytes(addr7, &buf[..1 + len]).await
     }
 
     async fn write_pwm_page(&mut self, addr7: u8, pwm: &[u8; LED_PWM_LENGTH]) -> Result<(), CkledError> {
         self.select_page(addr7, LED_PWM_PAGE).await?;
-        for (chunk_idx, chunk) in pwm.chunks(64).enumerate() {
-            let start = (chunk_idx * 64) as u8;
-            self.write_block(addr7, start, chunk).await?;
-        }
+        self.write_block(addr7, 0x00, pwm).await
+    }
+
+    /// Configure one driver and load the current front frame into it.
+    async fn init_driver(&mut self, di: usize) -> Result<(), CkledError> {
+        let addr = self.addrs[di];
+
+        // Function page setup
+        self.select_page(addr, FUNCTION_PAGE).await?;
+        self.write_reg(addr, CONFIGURATION_REG, MSKSW_SHUT_DOWN_MODE).await?;
+        self.write_reg(addr, PDU_REG, MSKSET_CA_CB_CHANNEL).await?;
+        self.write_reg(addr, SCAN_PHASE_REG, MSKPHASE_12CHANNEL).await?;
+        self.write_reg(addr, SLEW_RATE_CONTROL_MODE1_REG, MSKPWM_DELAY_PHASE_ENABLE).await?;
+        self.write_reg(addr, SLEW_RATE_CONTROL_MODE2_REG, MSKDRIVING_SINKING_CHANNEL_SLEWRATE_ENABLE).await?;
+        self.write_reg(addr, SOFTWARE_SLEEP_REG, MSKSLEEP_DISABLE).await?;
+
+        // LED control page: all off
+        self.select_page(addr, LED_CONTROL_PAGE).await?;
+        self.write_repeat(addr, 0x00, 0x00, LED_CONTROL_ON_OFF_LENGTH).await?;
+        self.led_ctrl[di].fill(0x00);
+        self.led_ctrl_dirty[di] = false;
+
+        // PWM: current frame
+        let pwm_copy = self.front[di];
+        self.write_pwm_page(addr, &pwm_copy).await?;
+        self.pwm_dirty[di] = false;
+
+        // Current tune page
+        self.select_page(addr, CURRENT_TUNE_PAGE).await?;
+        self.write_block(addr, 0x00, &DEFAULT_CURRENT_TUNE).await?;
+
+        // Enable LEDs
+        self.select_page(addr, LED_CONTROL_PAGE).await?;
+        self.write_repeat(addr, 0x00, 0xFF, LED_CONTROL_ON_OFF_LENGTH).await?;
+        self.led_ctrl[di].fill(0xFF);
+        self.led_ctrl_dirty[di] = false;
+
+        // Return normal mode
+        self.select_page(addr, FUNCTION_PAGE).await?;
+        self.write_reg(addr, CONFIGURATION_REG, MSKSW_NORMAL_MODE).await?;
+
         Ok(())
     }
 
     pub async fn init(&mut self) -> Result<(), CkledError> {
-        for di in 0..DRIVER_COUNT {
-            let addr = self.addrs[di];
+        self.back = [[0; LED_PWM_LENGTH]; DRIVER_COUNT];
+        self.front = [[0; LED_PWM_LENGTH]; DRIVER_COUNT];
 
-            // Function page setup
-            self.select_page(addr, FUNCTION_PAGE).await?;
-            self.write_reg(addr, CONFIGURATION_REG, MSKSW_SHUT_DOWN_MODE).await?;
-            self.write_reg(addr, PDU_REG, MSKSET_CA_CB_CHANNEL).await?;
-            self.write_reg(addr, SCAN_PHASE_REG, MSKPHASE_12CHANNEL).await?;
-            self.write_reg(addr, SLEW_RATE_CONTROL_MODE1_REG, MSKPWM_DELAY_PHASE_ENABLE).await?;
-            self.write_reg(addr, SLEW_RATE_CONTROL_MODE2_REG, MSKDRIVING_SINKING_CHANNEL_SLEWRATE_ENABLE).await?;
-            self.write_reg(addr, SOFTWARE_SLEEP_REG, MSKSLEEP_DISABLE).await?;
-
-            // LED control page: all off
-            self.select_page(addr, LED_CONTROL_PAGE).await?;
-            self.write_repeat(addr, 0x00, 0x00, LED_CONTROL_ON_OFF_LENGTH).await?;
-            self.led_ctrl[di].fill(0x00);
-            self.led_ctrl_dirty[di] = false;
-
-            // PWM: all 0
-            self.pwm[di].fill(0x00);
-            let pwm_copy = self.pwm[di];
-            self.write_pwm_page(addr, &pwm_copy).await?;
-            self.pwm_dirty[di] = false;
-
-            // Current tune page
-            self.select_page(addr, CURRENT_TUNE_PAGE).await?;
-            self.write_block(addr, 0x00, &DEFAULT_CURRENT_TUNE).await?;
-
-            // Enable LEDs
-            self.select_page(addr, LED_CONTROL_PAGE).await?;
-            self.write_repeat(addr, 0x00, 0xFF, LED_CONTROL_ON_OFF_LENGTH).await?;
-            self.led_ctrl[di].fill(0xFF);
-            self.led_ctrl_dirty[di] = false;
-
-            // Return normal mode
-            self.select_page(addr, FUNCTION_PAGE).await?;
-            self.write_reg(addr, CONFIGURATION_REG, MSKSW_NORMAL_MODE).await?;
+        for di in 0..DRIVER_COUNT {
+            self.init_driver(di).await?;
         }
 
         Ok(())
     }
 
-    #[expect(dead_code)]
+    /// Whether the function page of a driver still holds the configuration
+    /// written by [`init_driver`](Self::init_driver). A driver that went
+    /// through a power-on reset reads back its defaults, starting with
+    /// software shutdown mode.
+    async fn driver_configured(&mut self, di: usize) -> Result<bool, CkledError> {
+        let addr = self.addrs[di];
+
+        self.select_page(addr, FUNCTION_PAGE).await?;
+        for (reg, expected) in CONFIG_READBACK {
+            if self.read_reg(addr, reg).await? != expected {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Check every driver and re-initialize the ones that lost their
+    /// configuration, restoring the current frame. A driver that does not
+    /// answer is treated as reset too, so it is restored once it comes back.
+    ///
+    /// Returns whether any driver was restored. A driver that fails to
+    /// initialize does not keep the others from being restored; the first
+    /// error is returned after all of them were tried.
+    pub async fn recover(&mut self) -> Result<bool, CkledError> {
+        let mut restored = false;
+        let mut first_error = None;
+
+        for di in 0..DRIVER_COUNT {
+            if matches!(self.driver_configured(di).await, Ok(true)) {
+                continue;
+            }
+            match self.init_driver(di).await {
+                Ok(()) => restored = true,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(restored),
+        }
+    }
+
+    /// Render one LED into the back buffer. Nothing is shown until the
+    /// frame is [`present`](Self::present)ed and flushed.
     pub fn set_color(&mut self, led_index: usize, r: u8, g: u8, b: u8) {
         let Some(led) = self.led_at(led_index) else {
             return;
@@ -186,6 +254,8 @@ impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
         self.apply_pwm_to_led(led, rs, gs, bs);
     }
 
+    // Host builds of this file use it.
+    #[cfg_attr(target_os = "none", expect(dead_code))]
     pub async fn set_color_all(&mut self, r: u8, g: u8, b: u8, brightness: u8) -> Result<(), CkledError> {
         self.set_global_brightness_percent(brightness);
 
@@ -194,43 +264,57 @@ impl<'d, const DRIVER_COUNT: usize> Ckled2001<'d, DRIVER_COUNT> {
             self.apply_pwm_to_led(led, rs, gs, bs);
         }
 
+        self.present();
         self.flush().await
     }
 
+    /// Publish the back buffer as the next frame for [`flush`](Self::flush).
+    ///
+    /// The back buffer keeps its content, so the next frame can be rendered
+    /// incrementally. Only drivers whose page actually changed are marked for
+    /// writing.
+    pub fn present(&mut self) {
+        for di in 0..DRIVER_COUNT {
+            if self.front[di] != self.back[di] {
+                self.front[di] = self.back[di];
+                self.pwm_dirty[di] = true;
+            }
+        }
+    }
+
+    /// Send the presented frame to all drivers. The front buffer cannot change
+    /// while this runs, so every driver receives pages of the same frame.
     pub async fn flush(&mut self) -> Result<(), CkledError> {
+        let started = Instant::now();
+        let mut wrote = false;
+
         for di in 0..DRIVER_COUNT {
             let addr = self.addrs[di];
 
             if self.led_ctrl_dirty[di] {
+                let ctrl = self.led_ctrl[di];
                 self.select_page(addr, LED_CONTROL_PAGE).await?;
-
-                let mut offset = 0usize;
-                while offset < LED_CONTROL_ON_OFF_LENGTH {
-                    let n = (LED_CONTROL_ON_OFF_LENGTH - offset).min(64);
-                    let mut tmp = [0u8; 64];
-                    tmp[..n].copy_from_slice(&self.led_ctrl[di][offset..offset + n]);
-                    self.write_block(addr, offset as u8, &tmp[..n]).await?;
-                    offset += n;
-                }
-
+                self.write_block(addr, 0x00, &ctrl).await?;
                 self.led_ctrl_dirty[di] = false;
+                wrote = true;
             }
 
             if self.pwm_dirty[di] {
-                // Write PWM page (192 bytes) in 64-byte chunks.
-                self.select_page(addr, LED_PWM_PAGE).await?;
-
-                for chunk_idx in 0..(LED_PWM_LENGTH / 64) {
-                    let base = chunk_idx * 64;
-                    let mut tmp = [0u8; 64];
-                    tmp.copy_from_slice(&self.pwm[di][base..base + 64]);
-                    self.write_block(addr, base as u8, &tmp).await?;
-                }
-
+                let pwm = self.front[di];
+                self.write_pwm_page(addr, &pwm).await?;
                 self.pwm_dirty[di] = false;
+                wrote = true;
             }
         }
 
+        if wrote {
+            self.last_flush = started.elapsed();
+            self.max_flush = self.max_flush.max(self.last_flush);
+        }
         Ok(())
     }
+
+    /// Time spent by the last flush that wrote anything and by the longest
+    /// one since boot.
+    pub fn flush_times(&self) -> (Duration, Duration) { (self.last_flush, self.max_flush) }
 }
@@ -1,5 +1,14 @@
 #![allow(dead_code)]
 
+/// PWM register of each color channel of one LED, on driver `driver`.
+#[derive(Copy, Clone)]
+pub struct CkLed {
+    pub driver: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
 pub const A_1: u8 = 0x00;
 pub const A_2: u8 = 0x01;
 pub const A_3: u8 = 0x02;
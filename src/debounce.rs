@@ -0,0 +1,224 @@
+//! Debounce algorithms for the matrix scanner, which passes the time of
+//! each reading. rmk's own matrices can use them through [`DebouncerTrait`],
+//! one type per algorithm, with readings taken at the current time.
+
+use core::ops::RangeInclusive;
+use embassy_time::{Duration, Instant};
+use rmk::{
+    debounce::{DebounceState, DebouncerTrait},
+    matrix::KeyState,
+};
+
+/// Debounce time until one is configured.
+pub const DEFAULT_TIME: Duration = Duration::from_millis(10);
+/// Allowed debounce times in milliseconds.
+pub const TIME_MS: RangeInclusive<u8> = 1..=50;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Report a change once the key has read the new state for the whole
+    /// debounce time. Presses and releases both arrive one debounce time
+    /// late; short glitches never get through.
+    SymmetricDefer = 0,
+    /// Report a press on its first reading and ignore the key for the
+    /// debounce time after it; defer releases like [`Self::SymmetricDefer`].
+    /// Fastest presses, but a glitch on a released key reads as a tap.
+    EagerPress = 1,
+    /// Report any change on its first reading, then hold the key's whole
+    /// row for that key's debounce time. Other keys of that row changing
+    /// meanwhile are reported when the hold ends.
+    PerRowEager = 2,
+}
+
+impl Algorithm {
+    pub const fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::SymmetricDefer),
+            1 => Some(Self::EagerPress),
+            2 => Some(Self::PerRowEager),
+            _ => None,
+        }
+    }
+}
+
+/// What one reading did to a key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Reads as its debounced state, or is held after a change.
+    Unchanged,
+    /// Reads differently, not reported yet.
+    Pending,
+    /// The debounced state flips with this reading.
+    Changed,
+}
+
+#[derive(Copy, Clone)]
+enum KeyTimer {
+    Idle,
+    /// Has read differently from its debounced state since then.
+    Since(Instant),
+    /// Ignored until then after an eager change.
+    Until(Instant),
+}
+
+pub struct Debouncer<const ROW: usize, const COL: usize> {
+    algorithm: Algorithm,
+    time: Duration,
+    keys: [[KeyTimer; COL]; ROW],
+    /// Per-key debounce times in milliseconds, 0 for the default `time`.
+    key_ms: [[u8; COL]; ROW],
+    /// End of the hold of each row, for [`Algorithm::PerRowEager`].
+    rows: [Option<Instant>; ROW],
+}
+
+impl<const ROW: usize, const COL: usize> Debouncer<ROW, COL> {
+    pub const fn with_algorithm(algorithm: Algorithm, time: Duration) -> Self {
+        Self { algorithm, time, keys: [[KeyTimer::Idle; COL]; ROW], key_ms: [[0; COL]; ROW], rows: [None; ROW] }
+    }
+
+    /// Switch algorithm or default time, forgetting changes in progress.
+    /// Per-key times stay.
+    pub fn set(&mut self, algorithm: Algorithm, time: Duration) {
+        *self = Self { key_ms: self.key_ms, ..Self::with_algorithm(algorithm, time) };
+    }
+
+    /// Debounce key `(row, col)` for `ms` milliseconds instead of the default
+    /// time, or go back to the default with 0. Meant for single switches
+    /// that chatter; out-of-range keys are ignored.
+    pub fn set_key_time(&mut self, row: usize, col: usize, ms: u8) {
+        if let Some(key_ms) = self.key_ms.get_mut(row).and_then(|keys| keys.get_mut(col)) {
+            *key_ms = ms;
+        }
+    }
+
+    fn key_time(&self, row: usize, col: usize) -> Duration {
+        match self.key_ms.get(row).and_then(|keys| keys.get(col)) {
+            Some(&ms) if ms > 0 => Duration::from_millis(ms as u64),
+            _ => self.time,
+        }
+    }
+
+    /// Feed one reading of key `(row, col)` taken at `now`, with `state` its
+    /// debounced state. On [`Outcome::Changed`] the caller flips the state.
+    pub(crate) fn update(&mut self, row: usize, col: usize, raw: bool, state: bool, now: Instant) -> Outcome {
+        let time = self.key_time(row, col);
+        let (Some(timer), Some(hold)) =
+            (self.keys.get_mut(row).and_then(|keys| keys.get_mut(col)), self.rows.get_mut(row))
+        else {
+            return Outcome::Unchanged;
+        };
+
+        match self.algorithm {
+            Algorithm::SymmetricDefer => Self::defer(timer, raw != state, now, time),
+            Algorithm::EagerPress => {
+                if let KeyTimer::Until(end) = *timer {
+                    if now < end {
+                        return Outcome::Unchanged;
+                    }
+                    *timer = KeyTimer::Idle;
+                }
+                if raw && !state {
+                    *timer = KeyTimer::Until(now + time);
+                    return Outcome::Changed;
+                }
+                Self::defer(timer, raw != state, now, time)
+            }
+            Algorithm::PerRowEager => match *hold {
+                Some(end) if now < end => match raw != state {
+                    true => Outcome::Pending,
+                    false => Outcome::Unchanged,
+                },
+                _ if raw != state => {
+                    *hold = Some(now + time);
+                    Outcome::Changed
+                }
+                _ => Outcome::Unchanged,
+            },
+        }
+    }
+
+    /// Report once the key has differed for `time` without a break.
+    fn defer(timer: &mut KeyTimer, differs: bool, now: Instant, time: Duration) -> Outcome {
+        if !differs {
+            *timer = KeyTimer::Idle;
+            return Outcome::Unchanged;
+        }
+        match *timer {
+            KeyTimer::Since(start) if now - start >= time => {
+                *timer = KeyTimer::Idle;
+                Outcome::Changed
+            }
+            KeyTimer::Since(_) => Outcome::Pending,
+            _ => {
+                *timer = KeyTimer::Since(now);
+                Outcome::Pending
+            }
+        }
+    }
+
+    /// One reading for rmk, which passes the input pin as `in_idx`.
+    fn detect(&mut self, in_idx: usize, out_idx: usize, pin_state: bool, key_state: &KeyState) -> DebounceState {
+        match self.update(in_idx, out_idx, pin_state, key_state.pressed, Instant::now()) {
+            Outcome::Changed => DebounceState::Debounced,
+            Outcome::Pending => DebounceState::InProgress,
+            Outcome::Unchanged => DebounceState::Ignored,
+        }
+    }
+}
+
+/// [`Algorithm::SymmetricDefer`] at [`DEFAULT_TIME`] for rmk's matrices.
+#[cfg_attr(target_os = "none", expect(dead_code, reason = "this board scans with ShiftRegMatrix"))]
+pub struct SymmetricDefer<const ROW: usize, const COL: usize>(Debouncer<ROW, COL>);
+
+impl<const ROW: usize, const COL: usize> DebouncerTrait for SymmetricDefer<ROW, COL> {
+    fn new() -> Self { Self(Debouncer::with_algorithm(Algorithm::SymmetricDefer, DEFAULT_TIME)) }
+
+    fn detect_change_with_debounce(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+    ) -> DebounceState {
+        self.0.detect(in_idx, out_idx, pin_state, key_state)
+    }
+}
+
+/// [`Algorithm::EagerPress`] at [`DEFAULT_TIME`] for rmk's matrices.
+#[cfg_attr(target_os = "none", expect(dead_code, reason = "this board scans with ShiftRegMatrix"))]
+pub struct EagerPress<const ROW: usize, const COL: usize>(Debouncer<ROW, COL>);
+
+impl<const ROW: usize, const COL: usize> DebouncerTrait for EagerPress<ROW, COL> {
+    fn new() -> Self { Self(Debouncer::with_algorithm(Algorithm::EagerPress, DEFAULT_TIME)) }
+
+    fn detect_change_with_debounce(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+    ) -> DebounceState {
+        self.0.detect(in_idx, out_idx, pin_state, key_state)
+    }
+}
+
+/// [`Algorithm::PerRowEager`] at [`DEFAULT_TIME`] for rmk's matrices. rmk
+/// matrices pass the input pin as `in_idx`, so the held "row" is whichever
+/// side of the matrix they read.
+#[cfg_attr(target_os = "none", expect(dead_code, reason = "this board scans with ShiftRegMatrix"))]
+pub struct PerRowEager<const ROW: usize, const COL: usize>(Debouncer<ROW, COL>);
+
+impl<const ROW: usize, const COL: usize> DebouncerTrait for PerRowEager<ROW, COL> {
+    fn new() -> Self { Self(Debouncer::with_algorithm(Algorithm::PerRowEager, DEFAULT_TIME)) }
+
+    fn detect_change_with_debounce(
+        &mut self,
+        in_idx: usize,
+        out_idx: usize,
+        pin_state: bool,
+        key_state: &KeyState,
+    ) -> DebounceState {
+        self.0.detect(in_idx, out_idx, pin_state, key_state)
+    }
+}
@@ -0,0 +1,71 @@
+//! Checksummed settings records, one per flash page range, kept outside rmk's
+//! own storage sectors.
+//!
+//! Layout: `magic: u16 | version: u8 | 0xFF | len: u16 | crc16: u16 | payload`,
+//! all little-endian, with the payload padded to the flash write size.
+
+use embedded_storage_async::nor_flash::NorFlash;
+
+const MAGIC: u16 = 0x5131;
+const HEADER_LEN: usize = 8;
+const CHUNK_LEN: usize = 32;
+
+#[derive(Debug, Copy, Clone)]
+pub enum RecordError {
+    Flash,
+    Missing,
+    Corrupt,
+}
+
+/// CRC-16/CCITT-FALSE.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Read the record at `offset` into `buf`. The stored version and length must
+/// match exactly, otherwise the record is treated as missing.
+pub async fn load<F: NorFlash>(flash: &mut F, offset: u32, version: u8, buf: &mut [u8]) -> Result<(), RecordError> {
+    let mut header = [0u8; HEADER_LEN];
+    flash.read(offset, &mut header).await.map_err(|_| RecordError::Flash)?;
+
+    let magic = u16::from_le_bytes([header[0], header[1]]);
+    let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+    if magic != MAGIC || header[2] != version || len != buf.len() {
+        return Err(RecordError::Missing);
+    }
+
+    flash.read(offset + HEADER_LEN as u32, buf).await.map_err(|_| RecordError::Flash)?;
+    if crc16(buf) != u16::from_le_bytes([header[6], header[7]]) {
+        return Err(RecordError::Corrupt);
+    }
+    Ok(())
+}
+
+/// Erase the pages covering the record at `offset` and write `data` there.
+pub async fn save<F: NorFlash>(flash: &mut F, offset: u32, version: u8, data: &[u8]) -> Result<(), RecordError> {
+    let erase_len = (HEADER_LEN + data.len()).next_multiple_of(F::ERASE_SIZE) as u32;
+    flash.erase(offset, offset + erase_len).await.map_err(|_| RecordError::Flash)?;
+
+    let [m0, m1] = MAGIC.to_le_bytes();
+    let [l0, l1] = (data.len() as u16).to_le_bytes();
+    let [c0, c1] = crc16(data).to_le_bytes();
+    flash.write(offset, &[m0, m1, version, 0xFF, l0, l1, c0, c1]).await.map_err(|_| RecordError::Flash)?;
+
+    let mut chunk = [0xFFu8; CHUNK_LEN];
+    let mut addr = offset + HEADER_LEN as u32;
+    for part in data.chunks(CHUNK_LEN) {
+        let len = part.len().next_multiple_of(F::WRITE_SIZE);
+        chunk[..part.len()].copy_from_slice(part);
+        chunk[part.len()..len].fill(0xFF);
+        flash.write(addr, &chunk[..len]).await.map_err(|_| RecordError::Flash)?;
+        addr += CHUNK_LEN as u32;
+    }
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use crate::matrix_io::{ColumnDriver, RowReader};
+use core::convert::Infallible;
+use embassy_futures::select::select_array;
+use embassy_stm32::{exti::ExtiInput, gpio::Output};
+
+/// Rows pulled up, read low while a key in an active column is pressed.
+impl<'d, const ROW: usize> RowReader for [ExtiInput<'d>; ROW] {
+    #[inline]
+    fn is_pressed(&self, row: usize) -> bool { self.get(row).is_some_and(|pin| pin.is_low()) }
+
+    /// `wait_for_low` arms the falling edge interrupt before checking the
+    /// level, so a press while the columns switch is not missed.
+    async fn wait_for_any(&mut self) { select_array(self.each_mut().map(|pin| pin.wait_for_low())).await; }
+}
+
+/// One GPIO per column, active low, as on PCBs without a shift register.
+#[expect(dead_code, reason = "this board drives its columns through Hc595Cols")]
+pub struct GpioCols<'d, const COL: usize> {
+    pins: [Output<'d>; COL],
+}
+
+impl<'d, const COL: usize> GpioCols<'d, COL> {
+    #[expect(dead_code, reason = "this board drives its columns through Hc595Cols")]
+    pub fn new(pins: [Output<'d>; COL]) -> Self { Self { pins } }
+}
+
+impl<'d, const COL: usize> ColumnDriver for GpioCols<'d, COL> {
+    type Error = Infallible;
+
+    async fn select(&mut self, col: usize) -> Result<(), Infallible> {
+        for (i, pin) in self.pins.iter_mut().enumerate() {
+            match i == col {
+                true => pin.set_low(),
+                false => pin.set_high(),
+            }
+        }
+        Ok(())
+    }
+
+    async fn unselect_all(&mut self) -> Result<(), Infallible> {
+        self.pins.iter_mut().for_each(|pin| pin.set_high());
+        Ok(())
+    }
+
+    async fn select_all(&mut self) -> Result<(), Infallible> {
+        self.pins.iter_mut().for_each(|pin| pin.set_low());
+        Ok(())
+    }
+}
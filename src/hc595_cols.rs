@@ -1,13 +1,42 @@
-use embassy_stm32::gpio::Output;
+use crate::matrix_io::ColumnDriver;
+use embassy_stm32::{
+    gpio::Output,
+    mode::Async,
+    spi::{self, Spi},
+};
 
+/// How the 16 column bits get into the shift registers.
+enum Bus<'d> {
+    /// SER and SRCLK toggled in software.
+    BitBang { data: Output<'d>, clk: Output<'d> },
+    /// SER on MOSI and SRCLK on SCK, sent by DMA. The bus must be set up
+    /// LSB first in SPI mode 0.
+    Spi(Spi<'d, Async>),
+}
+
+/// Columns driven through two chained 74HC595s, active low.
+///
+/// Estimated cost per column select at 80 MHz, from cycle counts and the
+/// SPI clock; these figures were not measured on the board. To compare,
+/// build with and without `HC595_OVER_SPI` and read the pass time from the
+/// matrix scan counters. Bit-banging would take about 250
+/// cycles (~3 us) of CPU time; SPI at 10 MHz ~1.6 us on the wire plus ~2 us
+/// of DMA setup and completion, during which the executor can run other
+/// tasks. A full scan does 32 selects, so that would be ~100 us of busy CPU
+/// against ~115 us mostly spent waiting; either is small next to the
+/// 16 x 30 us of column settle time.
 pub struct Hc595Cols<'d> {
-    data: Output<'d>,
-    clk: Output<'d>,
+    bus: Bus<'d>,
     latch: Output<'d>,
 }
 
 impl<'d> Hc595Cols<'d> {
-    pub fn new(data: Output<'d>, clk: Output<'d>, latch: Output<'d>) -> Self { Self { data, clk, latch } }
+    pub fn new(data: Output<'d>, clk: Output<'d>, latch: Output<'d>) -> Self {
+        Self { bus: Bus::BitBang { data, clk }, latch }
+    }
+
+    /// Use an SPI bus configured LSB first in mode 0, with a TX DMA channel.
+    pub fn new_spi(spi: Spi<'d, Async>, latch: Output<'d>) -> Self { Self { bus: Bus::Spi(spi), latch } }
 
     #[inline(always)]
     fn pulse(clk: &mut Output<'d>) {
@@ -15,27 +44,45 @@ impl<'d> Hc595Cols<'d> {
         clk.set_low();
     }
 
-    /// Shift out 16 bits LSB-first, then latch.
-    pub fn write_u16_lsb_first(&mut self, mut v: u16) {
+    /// Shift out 16 bits LSB-first, then latch. On an SPI error nothing is
+    /// latched, so the outputs keep their last value.
+    pub async fn write_u16_lsb_first(&mut self, mut v: u16) -> Result<(), spi::Error> {
         self.latch.set_low();
-        for _ in 0..16 {
-            match v & 1 {
-                0 => self.data.set_low(),
-                _ => self.data.set_high(),
+        match &mut self.bus {
+            Bus::BitBang { data, clk } => {
+                for _ in 0..16 {
+                    match v & 1 {
+                        0 => data.set_low(),
+                        _ => data.set_high(),
+                    }
+                    Self::pulse(clk);
+                    v >>= 1;
+                }
             }
-            Self::pulse(&mut self.clk);
-            v >>= 1;
+            // Low byte first, each byte LSB first: the same bit order as
+            // above. The write returns once the last bit is clocked out.
+            Bus::Spi(spi) => spi.write(&v.to_le_bytes()).await?,
         }
         self.latch.set_high();
         self.latch.set_low();
+        Ok(())
     }
+}
 
-    /// Unselect all cols => all ones
-    pub fn unselect_all(&mut self) { self.write_u16_lsb_first(0xFFFF); }
+impl<'d> ColumnDriver for Hc595Cols<'d> {
+    /// Only the SPI bus can fail; bit-banging always goes through.
+    type Error = spi::Error;
 
     /// Select one col => all ones except selected bit is 0 (active-low)
-    pub fn select_col_active_low(&mut self, col: usize) {
+    async fn select(&mut self, col: usize) -> Result<(), spi::Error> {
+        debug_assert!(col < 16, "the shift registers drive 16 columns");
         let mask = 1u16 << (15 - col);
-        self.write_u16_lsb_first(!mask);
+        self.write_u16_lsb_first(!mask).await
     }
+
+    /// Unselect all cols => all ones
+    async fn unselect_all(&mut self) -> Result<(), spi::Error> { self.write_u16_lsb_first(0xFFFF).await }
+
+    /// Select all cols => all zeros, so any pressed key pulls its row low
+    async fn select_all(&mut self) -> Result<(), spi::Error> { self.write_u16_lsb_first(0x0000).await }
 }
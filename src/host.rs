@@ -0,0 +1,161 @@
+//! Keyboard-specific host commands carried in raw HID reports.
+//!
+//! Reports use the layout of VIA's custom value commands,
+//! `[command, channel, value id, data...]`, on channel ids above the ones VIA
+//! reserves for QMK features. Each request is answered with one reply: the
+//! request echoed back with the value data filled in, or with the command
+//! byte set to [`UNHANDLED`].
+//!
+//! A raw HID transport hands each report to [`handle_report`] and sends back
+//! what it returns. None is wired up yet: rmk 0.8 answers every raw HID
+//! report in its own Vial service and has no way to pass unknown commands on
+//! to the application. Until it does, or the firmware carries a patched rmk,
+//! nothing here can be reached from a computer. The handlers are exercised
+//! through [`handle_report`] by the host tests.
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, mutex::Mutex};
+
+pub const REPORT_LENGTH: usize = 32;
+
+pub type Report = [u8; REPORT_LENGTH];
+
+/// VIA's keyboard value query, `[command, value id, data...]`.
+pub const VIA_GET_KEYBOARD_VALUE: u8 = 0x02;
+/// Debounced switch state, `[rows...]` with each row as `COL / 8` bytes,
+/// most significant first. Vial's matrix tester sends this query, but rmk
+/// 0.8 answers it in its own Vial service from rmk's matrix state, so only
+/// [`handle_report`] reaches the answer here.
+pub const VIA_SWITCH_MATRIX_STATE: u8 = 0x03;
+
+pub const CUSTOM_SET_VALUE: u8 = 0x07;
+pub const CUSTOM_GET_VALUE: u8 = 0x08;
+pub const CUSTOM_SAVE: u8 = 0x09;
+pub const UNHANDLED: u8 = 0xFF;
+
+pub const CHANNEL_LIGHTING: u8 = 0x20;
+pub const CHANNEL_MATRIX: u8 = 0x21;
+
+/// Global brightness in percent: `[percent]`.
+pub const LIGHTING_BRIGHTNESS: u8 = 0x01;
+/// Effect of a layer theme: `[layer, effect]`.
+pub const LIGHTING_THEME_EFFECT: u8 = 0x02;
+/// Base color of a layer theme: `[layer, h, s, v]`.
+pub const LIGHTING_THEME_COLOR: u8 = 0x03;
+/// Animation speed of a layer theme: `[layer, speed]`.
+pub const LIGHTING_THEME_SPEED: u8 = 0x04;
+/// Static color of one LED in a layer theme: `[layer, led, h, s, v]`.
+pub const LIGHTING_THEME_KEY_COLOR: u8 = 0x05;
+/// Backlight factory test state, read only:
+/// `[active, led, row, col]`, with `0xFF` when no single LED is lit.
+pub const LIGHTING_TEST_STATUS: u8 = 0x06;
+/// Flash a color over some keys for a while, write only:
+/// `[h, s, v, pattern, duration_lo, duration_hi, mask...]`, see
+/// `lighting::overlay::Notification`.
+pub const LIGHTING_NOTIFY: u8 = 0x07;
+/// LED driver flush time in microseconds, read only:
+/// `[last_lo, last_hi, max_lo, max_hi]`, saturating at `0xFFFF`.
+pub const LIGHTING_FLUSH_TIME: u8 = 0x08;
+/// Part of a custom effect program being uploaded, write only:
+/// `[offset_lo, offset_hi, len, bytes...]`, at most [`PROGRAM_CHUNK_LEN`]
+/// bytes per report.
+pub const LIGHTING_PROGRAM_DATA: u8 = 0x09;
+/// Check the first `len` uploaded bytes, run them as the custom effect and
+/// save them: `[len_lo, len_hi]`. Reading returns the length of the active
+/// program.
+pub const LIGHTING_PROGRAM_COMMIT: u8 = 0x0A;
+/// How an effect reacts to the knob: `[effect, reaction]`, see
+/// `lighting::knob::KnobReaction`.
+pub const LIGHTING_KNOB_REACTION: u8 = 0x0B;
+/// Crossfade time between lighting changes in 10 ms units: `[time]`.
+pub const LIGHTING_TRANSITION: u8 = 0x0C;
+/// Minutes without input before the backlight fades out, 0 for never:
+/// `[minutes]`.
+pub const LIGHTING_IDLE_TIMEOUT: u8 = 0x0D;
+
+/// Column settle time of the matrix scan in microseconds: `[us_lo, us_hi]`,
+/// see `ScanTiming::SETTLE_US` for the allowed range.
+pub const MATRIX_SETTLE_US: u8 = 0x01;
+/// Pause between matrix passes while keys are held, in microseconds:
+/// `[us_lo, us_hi]`, see `ScanTiming::IDLE_US`.
+pub const MATRIX_IDLE_US: u8 = 0x02;
+/// Debounce algorithm: `[algorithm]`, see `debounce::Algorithm`.
+pub const MATRIX_DEBOUNCE_ALGORITHM: u8 = 0x03;
+/// Debounce time in milliseconds: `[ms]`, see `debounce::TIME_MS` for the
+/// allowed range.
+pub const MATRIX_DEBOUNCE_MS: u8 = 0x04;
+/// Debounce time of one key in milliseconds, 0 to use
+/// [`MATRIX_DEBOUNCE_MS`]: `[row, col, ms]`. Reading takes `[row, col]`.
+pub const MATRIX_KEY_DEBOUNCE_MS: u8 = 0x05;
+/// Keys that chattered or are stuck, read from the `first` one on:
+/// `[first]`, answered with `[count, (row, col, flags, chatters) x 7]`,
+/// unused entries zeroed. See `key_health` for the flags. Writing clears the
+/// chatter counts.
+pub const MATRIX_KEY_HEALTH: u8 = 0x06;
+/// Report stuck keys as released until they are let go: `[on]`.
+pub const MATRIX_MASK_STUCK: u8 = 0x07;
+/// Scan rate and input latency counters since boot, read only:
+/// `[scans_per_s, pass_avg_us, pass_max_us, post_avg_us, post_max_us,
+/// press_waits: u32, idle_waits: u32, column_errors: u32]`, see
+/// `scan_stats::ScanStats::encode`.
+/// Writing resets them.
+pub const MATRIX_SCAN_STATS: u8 = 0x08;
+
+/// Program bytes carried by one [`LIGHTING_PROGRAM_DATA`] report.
+pub const PROGRAM_CHUNK_LEN: usize = REPORT_LENGTH - DATA - 3;
+
+/// Offset of the first value data byte in a report.
+pub const DATA: usize = 3;
+
+/// Held for one request and its reply, so concurrent callers of
+/// [`handle_report`] each get their own reply.
+static EXCHANGE: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+/// Reports received from the host, in arrival order.
+pub static HOST_REQUESTS: Channel<CriticalSectionRawMutex, Report, 2> = Channel::new();
+/// Replies to [`HOST_REQUESTS`], one per request.
+pub static HOST_REPLIES: Channel<CriticalSectionRawMutex, Report, 2> = Channel::new();
+
+/// Requests on [`CHANNEL_LIGHTING`], served by the backlight controller.
+pub static LIGHTING_REQUESTS: Channel<CriticalSectionRawMutex, Report, 2> = Channel::new();
+/// Requests on [`CHANNEL_MATRIX`] and [`VIA_SWITCH_MATRIX_STATE`] queries,
+/// served by the matrix settings.
+pub static MATRIX_REQUESTS: Channel<CriticalSectionRawMutex, Report, 2> = Channel::new();
+
+#[inline]
+pub const fn command(report: &Report) -> u8 { report[0] }
+
+#[inline]
+pub const fn channel(report: &Report) -> u8 { report[1] }
+
+#[inline]
+pub const fn value_id(report: &Report) -> u8 { report[2] }
+
+/// Mark a request as not understood and send it back.
+pub async fn reply_unhandled(mut report: Report) {
+    report[0] = UNHANDLED;
+    HOST_REPLIES.send(report).await;
+}
+
+/// Serve one report from the host and return the reply to send back. This
+/// is the entry point for a raw HID transport; [`run`] and the subsystems'
+/// own tasks must be running.
+// The host tests call it.
+#[cfg_attr(target_os = "none", expect(dead_code, reason = "rmk 0.8 does not pass raw HID reports to the application"))]
+pub async fn handle_report(report: Report) -> Report {
+    let _exchange = EXCHANGE.lock().await;
+    HOST_REQUESTS.send(report).await;
+    HOST_REPLIES.receive().await
+}
+
+/// Route host requests to the subsystem owning their channel.
+pub async fn run() {
+    loop {
+        let report = HOST_REQUESTS.receive().await;
+        match (command(&report), channel(&report)) {
+            (VIA_GET_KEYBOARD_VALUE, VIA_SWITCH_MATRIX_STATE) => MATRIX_REQUESTS.send(report).await,
+            (_, CHANNEL_LIGHTING) => LIGHTING_REQUESTS.send(report).await,
+            (_, CHANNEL_MATRIX) => MATRIX_REQUESTS.send(report).await,
+            _ => reply_unhandled(report).await,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use rmk::{
+    event::{Event, KeyboardEvent},
+    input_device::InputDevice,
+};
+
+static GRABBED: AtomicBool = AtomicBool::new(false);
+
+/// Events taken away from rmk while input is grabbed.
+pub static GRABBED_EVENTS: Channel<CriticalSectionRawMutex, Event, 8> = Channel::new();
+
+/// Route input to [`GRABBED_EVENTS`] instead of the keymap, e.g. while a
+/// board mode uses the keys for itself.
+pub fn grab() { GRABBED.store(true, Ordering::Relaxed); }
+
+pub fn release() { GRABBED.store(false, Ordering::Relaxed); }
+
+pub fn is_grabbed() -> bool { GRABBED.load(Ordering::Relaxed) }
+
+/// Pass `event` back if it is for rmk, otherwise move it to
+/// [`GRABBED_EVENTS`]. Key releases always go to rmk so keys pressed before
+/// the grab never get stuck.
+pub fn route(event: Event) -> Option<Event> {
+    match event {
+        Event::Key(KeyboardEvent { pressed: false, .. }) => Some(event),
+        _ if !is_grabbed() => Some(event),
+        _ => {
+            let _ = GRABBED_EVENTS.try_send(event);
+            None
+        }
+    }
+}
+
+/// Input device wrapper honoring [`grab`], see [`route`].
+pub struct Grabbable<D> {
+    inner: D,
+}
+
+impl<D> Grabbable<D> {
+    pub const fn new(inner: D) -> Self { Self { inner } }
+}
+
+impl<D: InputDevice> InputDevice for Grabbable<D> {
+    async fn read_event(&mut self) -> Event {
+        loop {
+            if let Some(event) = route(self.inner.read_event().await) {
+                return event;
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Switch diagnostics from the debounced key changes: chatter and stuck keys,
+//! with optional masking of stuck ones. Like the debouncer, this never reads
+//! the clock.
+
+use embassy_time::{Duration, Instant};
+
+/// Presses of one key closer together than this are counted as chatter. No
+/// one taps a key 25 times a second.
+pub const CHATTER_GAP: Duration = Duration::from_millis(40);
+/// Keys held longer than this are flagged as stuck.
+pub const STUCK_AFTER: Duration = Duration::from_secs(30);
+
+/// Bits of the flags byte reported per key.
+pub const FLAG_CHATTER: u8 = 1 << 0;
+pub const FLAG_STUCK: u8 = 1 << 1;
+pub const FLAG_MASKED: u8 = 1 << 2;
+
+#[derive(Copy, Clone)]
+struct KeyRecord {
+    last_press: Option<Instant>,
+    /// Start of the current hold.
+    pressed_since: Option<Instant>,
+    chatters: u8,
+    stuck: bool,
+    /// A release was reported while the key is still held.
+    masked: bool,
+}
+
+impl KeyRecord {
+    const NEW: Self = Self { last_press: None, pressed_since: None, chatters: 0, stuck: false, masked: false };
+
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.chatters > 0 {
+            flags |= FLAG_CHATTER;
+        }
+        if self.stuck {
+            flags |= FLAG_STUCK;
+        }
+        if self.masked {
+            flags |= FLAG_MASKED;
+        }
+        flags
+    }
+}
+
+/// Key that chattered since the counts were cleared, or is stuck right now.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Offender {
+    pub row: u8,
+    pub col: u8,
+    pub flags: u8,
+    /// Chattering presses, saturating at 255.
+    pub chatters: u8,
+}
+
+pub struct KeyHealth<const ROW: usize, const COL: usize> {
+    keys: [[KeyRecord; COL]; ROW],
+    mask_stuck: bool,
+}
+
+impl<const ROW: usize, const COL: usize> KeyHealth<ROW, COL> {
+    pub const fn new() -> Self { Self { keys: [[KeyRecord::NEW; COL]; ROW], mask_stuck: false } }
+
+    /// Report stuck keys as released until they really are released. A key
+    /// masked already stays masked until then.
+    #[inline]
+    pub fn set_mask_stuck(&mut self, on: bool) { self.mask_stuck = on; }
+
+    /// Record a debounced change of key `(row, col)` at `at`. Returns whether
+    /// to report it: the release ending a masked hold was reported already.
+    pub fn changed(&mut self, row: usize, col: usize, pressed: bool, at: Instant) -> bool {
+        let Some(key) = self.keys.get_mut(row).and_then(|keys| keys.get_mut(col)) else {
+            return true;
+        };
+
+        if !pressed {
+            key.pressed_since = None;
+            key.stuck = false;
+            return !core::mem::take(&mut key.masked);
+        }
+        if key.last_press.is_some_and(|last| at - last < CHATTER_GAP) {
+            key.chatters = key.chatters.saturating_add(1);
+        }
+        key.last_press = Some(at);
+        key.pressed_since = Some(at);
+        true
+    }
+
+    /// Flag keys held past [`STUCK_AFTER`] at `now`. With masking on,
+    /// `release(row, col)` is called for each key to mask; it returns false
+    /// when the release could not be queued, and the key is tried again on
+    /// the next call.
+    pub fn check_stuck(&mut self, now: Instant, mut release: impl FnMut(usize, usize) -> bool) {
+        for (row, keys) in self.keys.iter_mut().enumerate() {
+            for (col, key) in keys.iter_mut().enumerate() {
+                let Some(since) = key.pressed_since else {
+                    continue;
+                };
+                if now - since < STUCK_AFTER {
+                    continue;
+                }
+                key.stuck = true;
+                if self.mask_stuck && !key.masked {
+                    key.masked = release(row, col);
+                }
+            }
+        }
+    }
+
+    /// Offending keys in matrix order.
+    pub fn offenders(&self) -> impl Iterator<Item = Offender> + '_ {
+        self.keys.iter().enumerate().flat_map(|(row, keys)| {
+            keys.iter().enumerate().filter(|(_, key)| key.flags() != 0).map(move |(col, key)| Offender {
+                row: row as u8,
+                col: col as u8,
+                flags: key.flags(),
+                chatters: key.chatters,
+            })
+        })
+    }
+
+    /// Forget the chatter counts, e.g. after replacing a switch.
+    pub fn clear_chatter(&mut self) {
+        for key in self.keys.iter_mut().flatten() {
+            key.chatters = 0;
+        }
+    }
+}
+
+impl<const ROW: usize, const COL: usize> Default for KeyHealth<ROW, COL> {
+    fn default() -> Self { Self::new() }
+}
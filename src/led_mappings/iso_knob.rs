@@ -1,6 +1,8 @@
-use crate::ckled2001::{driver::CkLed, led_address::*};
+use crate::ckled2001::led_address::*;
 
-pub const LED_LAYOUT: &[CkLed] = &[
+pub const LED_COUNT: usize = 83;
+
+pub const LED_LAYOUT: &[CkLed; LED_COUNT] = &[
     // Row 0 (first block)
     CkLed { driver: 0, r: I_1, g: G_1, b: H_1 },
     CkLed { driver: 0, r: I_2, g: G_2, b: H_2 },
@@ -91,3 +93,35 @@ pub const LED_LAYOUT: &[CkLed] = &[
     CkLed { driver: 1, r: F_2, g: D_2, b: E_2 },
     CkLed { driver: 1, r: F_1, g: D_1, b: E_1 },
 ];
+
+/// Matrix position `(row, col)` of the key above each LED, in `LED_LAYOUT`
+/// order.
+#[rustfmt::skip]
+pub const LED_MATRIX: [(u8, u8); LED_COUNT] = [
+    (0, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (0, 6), (0, 7), (0, 8), (0, 9), (0, 10), (0, 11), (0, 12), (0, 13), (0, 15),
+    (1, 0), (1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (1, 6), (1, 7), (1, 8), (1, 9), (1, 10), (1, 11), (1, 12), (1, 13), (1, 15),
+    (2, 0), (2, 1), (2, 2), (2, 3), (2, 4), (2, 5), (2, 6), (2, 7), (2, 8), (2, 9), (2, 10), (2, 11), (2, 12), (2, 13), (2, 15),
+    (3, 0), (3, 1), (3, 2), (3, 3), (3, 4), (3, 5), (3, 6), (3, 7), (3, 8), (3, 9), (3, 10), (3, 11), (3, 13), (3, 15),
+    (4, 0), (4, 1), (4, 2), (4, 3), (4, 4), (4, 5), (4, 6), (4, 7), (4, 8), (4, 9), (4, 10), (4, 11), (4, 13), (4, 14),
+    (5, 0), (5, 1), (5, 2), (5, 6), (5, 10), (5, 11), (5, 12), (5, 13), (5, 14), (5, 15),
+];
+
+/// Center `(x, y)` of the key above each LED, in `LED_LAYOUT` order, taken
+/// from the key sizes and offsets in `vial.json`. Both axes use 14 units per
+/// `u`, so distances and angles are not skewed; the board spans 0..=227 by
+/// 0..=91.
+#[rustfmt::skip]
+pub const LED_POSITION: [(u8, u8); LED_COUNT] = [
+    (7, 7), (25, 7), (39, 7), (53, 7), (67, 7), (84, 7), (98, 7), (112, 7), (126, 7), (144, 7), (158, 7), (172, 7), (186, 7), (203, 7), (221, 7),
+    (7, 25), (21, 25), (35, 25), (49, 25), (63, 25), (77, 25), (91, 25), (105, 25), (119, 25), (133, 25), (147, 25), (161, 25), (175, 25), (196, 25), (221, 25),
+    (11, 39), (28, 39), (42, 39), (56, 39), (70, 39), (84, 39), (98, 39), (112, 39), (126, 39), (140, 39), (154, 39), (168, 39), (182, 39), (201, 46), (221, 39),
+    (12, 53), (32, 53), (46, 53), (60, 53), (74, 53), (88, 53), (102, 53), (116, 53), (130, 53), (144, 53), (158, 53), (172, 53), (186, 53), (221, 53),
+    (9, 67), (25, 67), (39, 67), (53, 67), (67, 67), (81, 67), (95, 67), (109, 67), (123, 67), (137, 67), (151, 67), (165, 67), (184, 67), (207, 70),
+    (9, 81), (26, 81), (44, 81), (96, 81), (147, 81), (161, 81), (175, 81), (193, 84), (207, 84), (221, 84),
+];
+
+/// Center of the knob, the origin of radial effects.
+pub const KNOB_POSITION: (u8, u8) = (221, 7);
+
+/// Index into `LED_LAYOUT` of the LED under the key at `(row, col)`.
+pub fn led_at_matrix(row: u8, col: u8) -> Option<usize> { LED_MATRIX.iter().position(|&pos| pos == (row, col)) }
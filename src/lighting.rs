@@ -0,0 +1,12 @@
+pub mod bytecode;
+pub mod color;
+pub mod controller;
+pub mod effect;
+pub mod knob;
+pub mod overlay;
+pub mod paint;
+pub mod setup;
+pub mod status;
+pub mod test_mode;
+pub mod theme;
+pub mod transition;
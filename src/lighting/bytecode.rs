@@ -0,0 +1,311 @@
+//! Sandboxed bytecode for user-defined lighting effects.
+//!
+//! A program is a stack machine run once per LED and frame. It reads inputs
+//! such as time, the key position and how long ago the key was hit, computes
+//! hue, saturation and value and ends with [`op::OUT`]. Values are `i32` with
+//! wrapping arithmetic. Hue wraps around the color wheel, its low byte is
+//! used; saturation and value are clamped to `0..=255`.
+//!
+//! Time restarts every [`TIME_PERIOD_MS`] (about 17 minutes), so `t` times
+//! any speed stays far from overflowing. The period is a multiple of 256 and
+//! of every power of two up to 2048, so hues like `t * speed / 2048` or `t /
+//! 8` continue smoothly across the restart.
+//!
+//! Programs are checked once by [`Program::new`]: every opcode must be known,
+//! operands complete and jumps must go forward onto an instruction, so a run
+//! always terminates. Runs are further capped by an instruction budget shared
+//! by all LEDs of a frame.
+
+use crate::lighting::color::Hsv;
+
+/// Largest program accepted.
+pub const MAX_PROGRAM_LEN: usize = 512;
+const STACK_DEPTH: usize = 16;
+/// Program the custom effect runs until one is uploaded: a rainbow drifting
+/// across the board with pressed keys flashing white, compiled from
+/// `tools/effects/ripple.fx`.
+pub const BUILTIN: &[u8] =
+    &[0x14, 0x11, 0x30, 0x10, 0x17, 0x32, 0x02, 0x00, 0x08, 0x33, 0x31, 0x15, 0x13, 0x01, 0x02, 0x33, 0x35, 0x16, 0x60];
+/// Period of [`op::TIME`]: `2^20` ms times 255 still fits in an `i32`.
+pub const TIME_PERIOD_MS: u32 = 1 << 20;
+
+/// Opcodes. Operands follow the opcode byte, little-endian.
+pub mod op {
+    /// Push a signed 8-bit operand.
+    pub const PUSH8: u8 = 0x01;
+    /// Push a signed 16-bit operand.
+    pub const PUSH16: u8 = 0x02;
+
+    /// Milliseconds since boot, wrapping every [`super::TIME_PERIOD_MS`].
+    pub const TIME: u8 = 0x10;
+    /// Key center, in the units of `LED_POSITION`.
+    pub const X: u8 = 0x11;
+    pub const Y: u8 = 0x12;
+    /// Milliseconds since the key was last pressed, `i32::MAX` if never.
+    pub const HIT: u8 = 0x13;
+    /// Base color of the theme.
+    pub const HUE: u8 = 0x14;
+    pub const SAT: u8 = 0x15;
+    pub const VAL: u8 = 0x16;
+    /// Animation speed of the theme.
+    pub const SPEED: u8 = 0x17;
+    /// Index of the LED being rendered.
+    pub const LED: u8 = 0x18;
+
+    pub const DUP: u8 = 0x20;
+    pub const DROP: u8 = 0x21;
+    pub const SWAP: u8 = 0x22;
+    pub const OVER: u8 = 0x23;
+
+    /// Binary operators pop `b`, then `a`, and push `a op b`. Division and
+    /// remainder by zero give zero; shifts use the low 5 bits of `b`;
+    /// comparisons push 1 or 0.
+    pub const ADD: u8 = 0x30;
+    pub const SUB: u8 = 0x31;
+    pub const MUL: u8 = 0x32;
+    pub const DIV: u8 = 0x33;
+    pub const MOD: u8 = 0x34;
+    pub const MIN: u8 = 0x35;
+    pub const MAX: u8 = 0x36;
+    pub const AND: u8 = 0x37;
+    pub const OR: u8 = 0x38;
+    pub const XOR: u8 = 0x39;
+    pub const SHL: u8 = 0x3A;
+    pub const SHR: u8 = 0x3B;
+    pub const LT: u8 = 0x3C;
+    pub const GT: u8 = 0x3D;
+    pub const EQ: u8 = 0x3E;
+
+    pub const NEG: u8 = 0x40;
+    pub const ABS: u8 = 0x41;
+    /// Logical not: 1 for zero, 0 otherwise.
+    pub const NOT: u8 = 0x42;
+    /// Triangle wave over the low byte: 0 -> 255 -> 0.
+    pub const TRI: u8 = 0x43;
+    /// Integer square root of the absolute value.
+    pub const SQRT: u8 = 0x44;
+
+    /// Skip the given number of bytes after the operand.
+    pub const JMP: u8 = 0x50;
+    /// Pop a value and skip the given number of bytes if it is zero.
+    pub const JZ: u8 = 0x51;
+
+    /// Pop value, saturation and hue and finish.
+    pub const OUT: u8 = 0x60;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VmError {
+    TooLong,
+    InvalidOpcode,
+    /// An operand runs past the end of the program.
+    Truncated,
+    /// A jump does not land on an instruction.
+    BadJump,
+    StackOverflow,
+    StackUnderflow,
+    /// The program ran off its end without [`op::OUT`].
+    NoOutput,
+    /// The frame's instruction budget ran out.
+    Budget,
+}
+
+/// What a program can read about the LED being rendered.
+#[derive(Copy, Clone, Default)]
+pub struct Inputs {
+    pub now_ms: u32,
+    pub pos: (u8, u8),
+    pub hit_ms: u32,
+    pub color: Hsv,
+    pub speed: u8,
+    pub led: u8,
+}
+
+/// Number of operand bytes following `opcode`, or `None` for an unknown one.
+const fn operand_len(opcode: u8) -> Option<usize> {
+    match opcode {
+        op::PUSH8 | op::JMP | op::JZ => Some(1),
+        op::PUSH16 => Some(2),
+        op::TIME..=op::LED | op::DUP..=op::OVER | op::ADD..=op::EQ | op::NEG..=op::SQRT | op::OUT => Some(0),
+        _ => None,
+    }
+}
+
+/// A verified program.
+#[derive(Copy, Clone)]
+pub struct Program {
+    code: [u8; MAX_PROGRAM_LEN],
+    len: usize,
+}
+
+impl Program {
+    /// The empty program, which never produces a color.
+    pub const EMPTY: Self = Self { code: [0; MAX_PROGRAM_LEN], len: 0 };
+
+    /// Check `code` and take a copy of it.
+    pub fn new(code: &[u8]) -> Result<Self, VmError> {
+        if code.len() > MAX_PROGRAM_LEN {
+            return Err(VmError::TooLong);
+        }
+
+        // Instruction starts, so jumps into operands are caught.
+        let mut starts = [false; MAX_PROGRAM_LEN + 1];
+        starts[code.len()] = true;
+        let mut pc = 0;
+        while pc < code.len() {
+            starts[pc] = true;
+            pc += 1 + operand_len(code[pc]).ok_or(VmError::InvalidOpcode)?;
+        }
+        if pc > code.len() {
+            return Err(VmError::Truncated);
+        }
+
+        let mut pc = 0;
+        while pc < code.len() {
+            let len = operand_len(code[pc]).unwrap_or(0);
+            if matches!(code[pc], op::JMP | op::JZ)
+                && !starts.get(pc + 2 + code[pc + 1] as usize).copied().unwrap_or(false)
+            {
+                return Err(VmError::BadJump);
+            }
+            pc += 1 + len;
+        }
+
+        let mut program = Self::EMPTY;
+        program.code[..code.len()].copy_from_slice(code);
+        program.len = code.len();
+        Ok(program)
+    }
+
+    #[inline]
+    pub fn code(&self) -> &[u8] { &self.code[..self.len] }
+
+    /// Run the program for one LED, taking one unit of `budget` per
+    /// instruction.
+    pub fn run(&self, inputs: &Inputs, budget: &mut u32) -> Result<Hsv, VmError> {
+        let code = self.code();
+        let mut stack = [0i32; STACK_DEPTH];
+        let mut sp = 0usize;
+        let mut pc = 0usize;
+
+        macro_rules! push {
+            ($v:expr) => {{
+                let v: i32 = $v;
+                *stack.get_mut(sp).ok_or(VmError::StackOverflow)? = v;
+                sp += 1;
+            }};
+        }
+        macro_rules! pop {
+            () => {{
+                sp = sp.checked_sub(1).ok_or(VmError::StackUnderflow)?;
+                stack[sp]
+            }};
+        }
+
+        while pc < code.len() {
+            *budget = budget.checked_sub(1).ok_or(VmError::Budget)?;
+            let opcode = code[pc];
+            pc += 1;
+
+            match opcode {
+                op::PUSH8 => {
+                    push!(code[pc] as i8 as i32);
+                    pc += 1;
+                }
+                op::PUSH16 => {
+                    push!(i16::from_le_bytes([code[pc], code[pc + 1]]) as i32);
+                    pc += 2;
+                }
+
+                op::TIME => push!((inputs.now_ms % TIME_PERIOD_MS) as i32),
+                op::X => push!(inputs.pos.0 as i32),
+                op::Y => push!(inputs.pos.1 as i32),
+                op::HIT => push!(inputs.hit_ms.min(i32::MAX as u32) as i32),
+                op::HUE => push!(inputs.color.h as i32),
+                op::SAT => push!(inputs.color.s as i32),
+                op::VAL => push!(inputs.color.v as i32),
+                op::SPEED => push!(inputs.speed as i32),
+                op::LED => push!(inputs.led as i32),
+
+                op::DUP => {
+                    let a = pop!();
+                    push!(a);
+                    push!(a);
+                }
+                op::DROP => {
+                    pop!();
+                }
+                op::SWAP => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(b);
+                    push!(a);
+                }
+                op::OVER => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(a);
+                    push!(b);
+                    push!(a);
+                }
+
+                op::ADD..=op::EQ => {
+                    let b = pop!();
+                    let a = pop!();
+                    push!(match opcode {
+                        op::ADD => a.wrapping_add(b),
+                        op::SUB => a.wrapping_sub(b),
+                        op::MUL => a.wrapping_mul(b),
+                        op::DIV => a.checked_div(b).unwrap_or(0),
+                        op::MOD => a.checked_rem(b).unwrap_or(0),
+                        op::MIN => a.min(b),
+                        op::MAX => a.max(b),
+                        op::AND => a & b,
+                        op::OR => a | b,
+                        op::XOR => a ^ b,
+                        op::SHL => a.wrapping_shl(b as u32),
+                        op::SHR => a.wrapping_shr(b as u32),
+                        op::LT => (a < b) as i32,
+                        op::GT => (a > b) as i32,
+                        _ => (a == b) as i32,
+                    });
+                }
+
+                op::NEG..=op::SQRT => {
+                    let a = pop!();
+                    push!(match opcode {
+                        op::NEG => a.wrapping_neg(),
+                        op::ABS => a.wrapping_abs(),
+                        op::NOT => (a == 0) as i32,
+                        op::TRI => {
+                            let phase = a as u8;
+                            (if phase < 128 { phase * 2 } else { (255 - phase) * 2 }) as i32
+                        }
+                        _ => a.unsigned_abs().isqrt() as i32,
+                    });
+                }
+
+                op::JMP => pc += 1 + code[pc] as usize,
+                op::JZ => {
+                    let skip = code[pc] as usize;
+                    pc += 1;
+                    if pop!() == 0 {
+                        pc += skip;
+                    }
+                }
+
+                op::OUT => {
+                    let channel = |v: i32| v.clamp(0, 255) as u8;
+                    let v = pop!();
+                    let s = pop!();
+                    let h = pop!();
+                    return Ok(Hsv::new((h & 0xFF) as u8, channel(s), channel(v)));
+                }
+
+                _ => return Err(VmError::InvalidOpcode),
+            }
+        }
+
+        Err(VmError::NoOutput)
+    }
+}
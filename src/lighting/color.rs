@@ -0,0 +1,71 @@
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self { Self { r, g, b } }
+
+    /// Same color scaled by `level / 255`.
+    #[inline]
+    pub const fn dimmed(self, level: u8) -> Self { Self::BLACK.blend(self, level) }
+
+    /// Mix towards `other` by `amount / 255`.
+    #[inline]
+    pub const fn blend(self, other: Self, amount: u8) -> Self {
+        const fn mix(a: u8, b: u8, amount: u8) -> u8 {
+            ((a as u16 * (255 - amount) as u16 + b as u16 * amount as u16 + 127) / 255) as u8
+        }
+        Self::new(mix(self.r, other.r, amount), mix(self.g, other.g, amount), mix(self.b, other.b, amount))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Hsv {
+    pub h: u8,
+    pub s: u8,
+    pub v: u8,
+}
+
+impl Hsv {
+    pub const WHITE: Self = Self::new(0, 0, 255);
+
+    pub const fn new(h: u8, s: u8, v: u8) -> Self { Self { h, s, v } }
+
+    /// Same color with the value scaled by `level / 255`.
+    #[inline]
+    pub const fn dimmed(self, level: u8) -> Self {
+        Self { v: ((self.v as u16 * level as u16 + 127) / 255) as u8, ..self }
+    }
+
+    /// Integer HSV to RGB conversion over a 0..=255 hue circle split into six
+    /// 43-step regions.
+    pub const fn to_rgb(self) -> Rgb {
+        if self.s == 0 {
+            return Rgb::new(self.v, self.v, self.v);
+        }
+
+        let region = self.h / 43;
+        let remainder = (self.h - region * 43) as u16 * 6;
+
+        let v = self.v as u16;
+        let s = self.s as u16;
+        let p = ((v * (255 - s)) >> 8) as u8;
+        let q = ((v * (255 - ((s * remainder) >> 8))) >> 8) as u8;
+        let t = ((v * (255 - ((s * (255 - remainder)) >> 8))) >> 8) as u8;
+
+        match region {
+            0 => Rgb::new(self.v, t, p),
+            1 => Rgb::new(q, self.v, p),
+            2 => Rgb::new(p, self.v, t),
+            3 => Rgb::new(p, q, self.v),
+            4 => Rgb::new(t, p, self.v),
+            _ => Rgb::new(self.v, p, q),
+        }
+    }
+}
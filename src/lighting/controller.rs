@@ -0,0 +1,639 @@
+use crate::{
+    ckled2001::driver::Ckled2001,
+    flash_record,
+    host::{self, DATA, HOST_REPLIES, LIGHTING_REQUESTS, PROGRAM_CHUNK_LEN, Report},
+    input_grab::{self, GRABBED_EVENTS},
+    led_mappings::iso_knob::{LED_COUNT, LED_POSITION, led_at_matrix},
+    lighting::{
+        bytecode::{BUILTIN, Inputs, MAX_PROGRAM_LEN, Program},
+        color::{Hsv, Rgb},
+        effect::Effect,
+        knob::{Knob, KnobReaction},
+        overlay::{NOTIFICATION_LEN, Notification},
+        paint::{PAINT_CHORD, PaintMode},
+        setup::{Exit, SETUP_CHORD, SetupMode},
+        status::StatusAnimation,
+        test_mode::TestMode,
+        theme::{LightingConfig, MAX_IDLE_MINUTES, MAX_TRANSITION, Theme},
+        transition::Transition,
+    },
+};
+use embassy_futures::select::{Either3, select3};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_async::i2c::I2c;
+use embedded_storage_async::nor_flash::NorFlash;
+use rmk::{
+    channel::{CONTROLLER_CHANNEL, ControllerSub},
+    controller::{Controller, PollingController},
+    event::{ControllerEvent, Direction, Event, KeyboardEvent, KeyboardEventPos, RotaryEncoderPos},
+};
+
+/// Offset of the lighting record inside the lighting flash partition.
+const CONFIG_OFFSET: u32 = 0;
+/// Custom effect program record: `len: u16` followed by the code, padded to
+/// [`MAX_PROGRAM_LEN`].
+const PROGRAM_VERSION: u8 = 1;
+const PROGRAM_RECORD_LEN: usize = 2 + MAX_PROGRAM_LEN;
+/// Bytecode instructions all LEDs together may run per frame. LEDs past the
+/// budget show the theme's base color.
+const FRAME_BUDGET: u32 = 8192;
+/// How often the LED drivers are read back to catch a reset.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum LightingEvent {
+    Controller(ControllerEvent),
+    Host(Report),
+    /// Input grabbed from the keymap by a lighting mode.
+    Input(Event),
+}
+
+/// What the backlight shows besides the layer themes.
+enum Mode {
+    Themes,
+    Test(TestMode),
+    Paint(PaintMode),
+    Setup(SetupMode),
+}
+
+/// Renders the theme of the active layer into the LED drivers and serves the
+/// lighting host channel.
+pub struct BacklightController<I: I2c, F: NorFlash, const DRIVER_COUNT: usize> {
+    driver: Ckled2001<I, DRIVER_COUNT>,
+    flash: F,
+    sub: ControllerSub,
+
+    config: LightingConfig,
+    layer: u8,
+    mode: Mode,
+    /// Keys of [`PAINT_CHORD`] and [`SETUP_CHORD`] currently held.
+    paint_held: [bool; PAINT_CHORD.len()],
+    setup_held: [bool; SETUP_CHORD.len()],
+    notification: Option<Notification>,
+    /// Custom effect, and the buffer a new one is uploaded into.
+    program: Program,
+    upload: [u8; MAX_PROGRAM_LEN],
+    /// When each key was last pressed, indexed like `LED_LAYOUT`.
+    hits: [Option<u32>; LED_COUNT],
+    /// Running status animation and its start time.
+    status: Option<(StatusAnimation, u32)>,
+    knob: Knob,
+    /// Colors sent with the last frame, brightness included, which a new
+    /// transition fades out of.
+    shown: [Rgb; LED_COUNT],
+    transition: Option<Transition>,
+    /// Faded out after [`LightingConfig::idle_minutes`] without input.
+    idle: bool,
+    last_input: Instant,
+    started: Instant,
+    last_health_check: Instant,
+}
+
+impl<I: I2c, F: NorFlash, const DRIVER_COUNT: usize> BacklightController<I, F, DRIVER_COUNT> {
+    pub fn new(mut driver: Ckled2001<I, DRIVER_COUNT>, flash: F) -> Self {
+        // Brightness is applied while rendering, so that changing it can be
+        // crossfaded like any other change.
+        driver.set_global_brightness_percent(100);
+        Self {
+            driver,
+            flash,
+            sub: CONTROLLER_CHANNEL.subscriber().unwrap(),
+            config: LightingConfig::default(),
+            layer: 0,
+            mode: Mode::Themes,
+            paint_held: [false; PAINT_CHORD.len()],
+            setup_held: [false; SETUP_CHORD.len()],
+            notification: None,
+            program: Program::new(BUILTIN).unwrap_or(Program::EMPTY),
+            upload: [0; MAX_PROGRAM_LEN],
+            hits: [None; LED_COUNT],
+            status: None,
+            knob: Knob::default(),
+            shown: [Rgb::BLACK; LED_COUNT],
+            transition: None,
+            idle: false,
+            last_input: Instant::now(),
+            started: Instant::now(),
+            last_health_check: Instant::now(),
+        }
+    }
+
+    /// Load the saved themes and custom effect, keeping the defaults if there
+    /// are none.
+    pub async fn restore(&mut self) {
+        let mut buf = [0u8; LightingConfig::ENCODED_LEN];
+        if flash_record::load(&mut self.flash, CONFIG_OFFSET, LightingConfig::VERSION, &mut buf).await.is_ok()
+            && let Some(config) = LightingConfig::decode(&buf)
+        {
+            self.config = config;
+        }
+
+        let mut buf = [0u8; PROGRAM_RECORD_LEN];
+        if flash_record::load(&mut self.flash, Self::program_offset(), PROGRAM_VERSION, &mut buf).await.is_ok() {
+            let len = (u16::from_le_bytes([buf[0], buf[1]]) as usize).min(MAX_PROGRAM_LEN);
+            if let Ok(program) = Program::new(&buf[2..2 + len]) {
+                self.program = program;
+            }
+        }
+    }
+
+    /// The program record starts on the first page after the themes.
+    #[inline]
+    fn program_offset() -> u32 { (8 + LightingConfig::ENCODED_LEN).next_multiple_of(F::ERASE_SIZE) as u32 }
+
+    #[inline]
+    fn now_ms(&self) -> u32 { self.started.elapsed().as_millis() as u32 }
+
+    /// Replace the themes with the backlight factory test and take over the
+    /// keys until the next power cycle.
+    pub fn start_test_mode(&mut self) {
+        self.mode = Mode::Test(TestMode::new(self.now_ms()));
+        input_grab::grab();
+    }
+
+    /// Run a status animation to the end, drawing the frames directly, before
+    /// the polling loop is running.
+    pub async fn play(&mut self, animation: StatusAnimation) {
+        let started_ms = self.now_ms();
+        self.status = Some((animation, started_ms));
+        loop {
+            let now_ms = self.now_ms();
+            self.draw(now_ms).await;
+            if now_ms.wrapping_sub(started_ms) >= animation.duration_ms() {
+                break;
+            }
+            Timer::after(Self::INTERVAL).await;
+        }
+        self.status = None;
+        self.begin_transition();
+    }
+
+    /// Crossfade from what is on the LEDs now to the frames drawn next.
+    fn begin_transition(&mut self) {
+        self.transition = Transition::start(&self.shown, self.now_ms(), self.config.transition_ms());
+    }
+
+    /// Note some input, fading the backlight back in if it was idle.
+    fn wake(&mut self) {
+        self.last_input = Instant::now();
+        if self.idle {
+            self.idle = false;
+            self.begin_transition();
+        }
+    }
+
+    fn set_layer(&mut self, layer: u8) {
+        if layer != self.layer {
+            self.layer = layer;
+            self.begin_transition();
+        }
+    }
+
+    fn record_hit(&mut self, event: KeyboardEvent) {
+        if let KeyboardEventPos::Key(pos) = event.pos
+            && event.pressed
+            && let Some(led_index) = led_at_matrix(pos.row, pos.col)
+        {
+            self.hits[led_index] = Some(self.now_ms());
+        }
+    }
+
+    /// Let the knob drive the lighting while the themes are shown. The
+    /// encoder still sends its keycodes; this only watches the turns.
+    fn follow_knob(&mut self, event: KeyboardEvent) {
+        let KeyboardEventPos::RotaryEncoder(RotaryEncoderPos { direction, .. }) = event.pos else {
+            return;
+        };
+        if !event.pressed || direction == Direction::None || !matches!(self.mode, Mode::Themes) {
+            return;
+        }
+
+        let reaction = self.config.knob(self.config.theme(self.layer).effect);
+        self.knob.turn(reaction, direction == Direction::Clockwise, self.now_ms());
+    }
+
+    /// Follow the keys reaching the keymap to spot [`PAINT_CHORD`] and
+    /// [`SETUP_CHORD`].
+    fn track_chord(&mut self, event: KeyboardEvent) {
+        let KeyboardEventPos::Key(pos) = event.pos else {
+            return;
+        };
+        let key = (pos.row, pos.col);
+        let paint = chord_completed(&mut self.paint_held, &PAINT_CHORD, key, event.pressed);
+        let setup = chord_completed(&mut self.setup_held, &SETUP_CHORD, key, event.pressed);
+        if !matches!(self.mode, Mode::Themes) {
+            return;
+        }
+
+        if paint {
+            self.mode = Mode::Paint(PaintMode::new(self.config.theme(self.layer).keys));
+            input_grab::grab();
+        } else if setup {
+            self.mode = Mode::Setup(SetupMode::new(self.config));
+            input_grab::grab();
+        }
+    }
+
+    /// Store the painted colors as the per-key scheme of the active layer, or
+    /// leave the theme as it was.
+    async fn finish_painting(&mut self, exit: Exit, keys: [Hsv; LED_COUNT]) {
+        if exit == Exit::Save {
+            if let Some(theme) = self.config.theme_mut(self.layer) {
+                theme.keys = keys;
+                theme.effect = Effect::PerKey;
+            }
+            self.save_or_warn().await;
+        }
+        self.begin_transition();
+
+        self.mode = Mode::Themes;
+        input_grab::release();
+    }
+
+    /// Keep or undo the setup changes and go back to the themes.
+    async fn finish_setup(&mut self, exit: Exit) {
+        match (exit, &self.mode) {
+            (Exit::Save, _) => self.save_or_warn().await,
+            (Exit::Discard, Mode::Setup(setup)) => {
+                self.config = *setup.saved();
+                self.begin_transition();
+            }
+            (Exit::Discard, _) => {}
+        }
+
+        self.mode = Mode::Themes;
+        input_grab::release();
+    }
+
+    async fn handle_input(&mut self, event: Event) {
+        self.wake();
+        let now_ms = self.now_ms();
+        let turn = match event {
+            Event::RotaryEncoder(encoder) if encoder.direction != Direction::None => {
+                Some(encoder.direction == Direction::Clockwise)
+            }
+            _ => None,
+        };
+        let press = match event {
+            Event::Key(KeyboardEvent { pressed: true, pos: KeyboardEventPos::Key(pos) }) => Some((pos.row, pos.col)),
+            _ => None,
+        };
+
+        match &mut self.mode {
+            Mode::Themes => {}
+            Mode::Test(test) => {
+                if let Some(forward) = turn.or(press.map(|_| true)) {
+                    test.step(forward, now_ms);
+                }
+            }
+            Mode::Paint(paint) => {
+                if let Some(clockwise) = turn {
+                    paint.turn(clockwise);
+                }
+                if let Some(exit) = press.and_then(|(row, col)| paint.press(row, col)) {
+                    let keys = *paint.keys();
+                    self.finish_painting(exit, keys).await;
+                }
+            }
+            Mode::Setup(setup) => {
+                if let Some(clockwise) = turn {
+                    setup.turn(&mut self.config, self.layer, clockwise);
+                }
+                let exit = press.and_then(|(row, col)| setup.press(row, col));
+                if turn.is_some() {
+                    self.begin_transition();
+                }
+                if let Some(exit) = exit {
+                    self.finish_setup(exit).await;
+                }
+            }
+        }
+    }
+
+    /// Run the custom effect for one LED, falling back to the base color when
+    /// there is no program or the frame budget is spent.
+    fn render_program(&self, theme: &Theme, led_index: usize, now_ms: u32, hue_shift: u8, budget: &mut u32) -> Rgb {
+        let color = Hsv { h: theme.color.h.wrapping_add(hue_shift), ..theme.color };
+        let inputs = Inputs {
+            now_ms,
+            pos: LED_POSITION.get(led_index).copied().unwrap_or_default(),
+            hit_ms: self.hits.get(led_index).copied().flatten().map_or(u32::MAX, |t| now_ms.wrapping_sub(t)),
+            color,
+            speed: theme.speed,
+            led: led_index as u8,
+        };
+        self.program.run(&inputs, budget).unwrap_or(color).to_rgb()
+    }
+
+    #[inline]
+    fn led_color(&self, led_index: usize, now_ms: u32, budget: &mut u32) -> Rgb {
+        if let Some((animation, started_ms)) = self.status {
+            return animation.render(led_index, now_ms.wrapping_sub(started_ms));
+        }
+        let color = if self.idle { Rgb::BLACK } else { self.mode_color(led_index, now_ms, budget) };
+        match &self.notification {
+            Some(notification) if !matches!(self.mode, Mode::Test(_)) => notification.over(led_index, now_ms, color),
+            _ => color,
+        }
+    }
+
+    #[inline]
+    fn mode_color(&self, led_index: usize, now_ms: u32, budget: &mut u32) -> Rgb {
+        match &self.mode {
+            Mode::Themes => {
+                let theme = self.config.theme(self.layer);
+                let reaction = self.config.knob(theme.effect);
+                let color = self.theme_color(theme, led_index, now_ms, self.knob.hue_shift(reaction), budget);
+                self.knob.render(reaction, led_index, now_ms, color)
+            }
+            Mode::Test(test) => test.render(led_index, now_ms),
+            Mode::Paint(paint) => paint.render(led_index, now_ms),
+            Mode::Setup(setup) => {
+                let color = self.theme_color(self.config.theme(self.layer), led_index, now_ms, 0, budget);
+                setup.render(led_index, now_ms, color)
+            }
+        }
+    }
+
+    #[inline]
+    fn theme_color(&self, theme: &Theme, led_index: usize, now_ms: u32, hue_shift: u8, budget: &mut u32) -> Rgb {
+        if theme.effect == Effect::Custom {
+            self.render_program(theme, led_index, now_ms, hue_shift, budget)
+        } else {
+            theme.render_shifted(led_index, now_ms, hue_shift)
+        }
+    }
+
+    /// Render one frame into the LED drivers.
+    async fn draw(&mut self, now_ms: u32) {
+        let brightness =
+            if matches!(self.mode, Mode::Test(_)) || self.status.is_some() { 100 } else { self.config.brightness };
+
+        let level = (brightness as u16 * 255 / 100) as u8;
+
+        let mut budget = FRAME_BUDGET;
+        for led_index in 0..LED_COUNT {
+            let color = self.led_color(led_index, now_ms, &mut budget).dimmed(level);
+            let c = match &self.transition {
+                Some(transition) => transition.blend(led_index, now_ms, color),
+                None => color,
+            };
+            self.shown[led_index] = c;
+            self.driver.set_color(led_index, c.r, c.g, c.b);
+        }
+        self.driver.present();
+        if let Err(e) = self.driver.flush().await {
+            defmt::warn!("LED flush failed: {}", defmt::Debug2Format(&e));
+        }
+    }
+
+    async fn save(&mut self) -> Result<(), flash_record::RecordError> {
+        let mut buf = [0u8; LightingConfig::ENCODED_LEN];
+        self.config.encode(&mut buf);
+        flash_record::save(&mut self.flash, CONFIG_OFFSET, LightingConfig::VERSION, &buf).await
+    }
+
+    async fn save_or_warn(&mut self) {
+        if let Err(e) = self.save().await {
+            defmt::warn!("saving the lighting config failed: {}", defmt::Debug2Format(&e));
+        }
+    }
+
+    /// Check the uploaded program, switch to it and save it.
+    async fn commit_program(&mut self, report: &Report) -> bool {
+        let len = u16::from_le_bytes([report[DATA], report[DATA + 1]]) as usize;
+        let Some(program) = self.upload.get(..len).and_then(|code| Program::new(code).ok()) else {
+            return false;
+        };
+        self.program = program;
+
+        let mut buf = [0u8; PROGRAM_RECORD_LEN];
+        buf[..2].copy_from_slice(&(len as u16).to_le_bytes());
+        buf[2..2 + len].copy_from_slice(program.code());
+        flash_record::save(&mut self.flash, Self::program_offset(), PROGRAM_VERSION, &buf).await.is_ok()
+    }
+
+    fn set_value(&mut self, report: &Report) -> bool {
+        let d = &report[DATA..];
+        match host::value_id(report) {
+            host::LIGHTING_BRIGHTNESS => {
+                self.config.brightness = d[0].min(100);
+                self.begin_transition();
+                return true;
+            }
+            host::LIGHTING_TRANSITION => {
+                if d[0] > MAX_TRANSITION {
+                    return false;
+                }
+                self.config.transition = d[0];
+                return true;
+            }
+            host::LIGHTING_IDLE_TIMEOUT => {
+                if d[0] > MAX_IDLE_MINUTES {
+                    return false;
+                }
+                self.config.idle_minutes = d[0];
+                return true;
+            }
+            host::LIGHTING_NOTIFY => {
+                let mut data = [0u8; NOTIFICATION_LEN];
+                data.copy_from_slice(&d[..NOTIFICATION_LEN]);
+                let Some(notification) = Notification::decode(&data, self.now_ms()) else {
+                    return false;
+                };
+                self.notification = Some(notification);
+                return true;
+            }
+            host::LIGHTING_PROGRAM_DATA => {
+                let offset = u16::from_le_bytes([d[0], d[1]]) as usize;
+                let len = (d[2] as usize).min(PROGRAM_CHUNK_LEN);
+                let Some(dst) = self.upload.get_mut(offset..offset + len) else {
+                    return false;
+                };
+                dst.copy_from_slice(&d[3..3 + len]);
+                return true;
+            }
+            host::LIGHTING_KNOB_REACTION => {
+                let (Some(effect), Some(reaction)) = (Effect::from_u8(d[0]), KnobReaction::from_u8(d[1])) else {
+                    return false;
+                };
+                self.config.knob[effect as usize] = reaction;
+                return true;
+            }
+            _ => {}
+        }
+
+        let Some(theme) = self.config.theme_mut(d[0]) else {
+            return false;
+        };
+        match host::value_id(report) {
+            host::LIGHTING_THEME_EFFECT => match Effect::from_u8(d[1]) {
+                Some(effect) => theme.effect = effect,
+                None => return false,
+            },
+            host::LIGHTING_THEME_COLOR => theme.color = Hsv::new(d[1], d[2], d[3]),
+            host::LIGHTING_THEME_SPEED => theme.speed = d[1],
+            host::LIGHTING_THEME_KEY_COLOR => match theme.keys.get_mut(d[1] as usize) {
+                Some(key) => *key = Hsv::new(d[2], d[3], d[4]),
+                None => return false,
+            },
+            _ => return false,
+        }
+        if d[0] == self.layer {
+            self.begin_transition();
+        }
+        true
+    }
+
+    fn get_value(&self, report: &mut Report) -> bool {
+        let id = host::value_id(report);
+        let d = &mut report[DATA..];
+        match id {
+            host::LIGHTING_BRIGHTNESS => {
+                d[0] = self.config.brightness;
+                return true;
+            }
+            host::LIGHTING_TRANSITION => {
+                d[0] = self.config.transition;
+                return true;
+            }
+            host::LIGHTING_IDLE_TIMEOUT => {
+                d[0] = self.config.idle_minutes;
+                return true;
+            }
+            host::LIGHTING_TEST_STATUS => {
+                let status = match &self.mode {
+                    Mode::Test(test) => test.status(self.now_ms()),
+                    _ => [0, 0xFF, 0xFF, 0xFF],
+                };
+                d[..4].copy_from_slice(&status);
+                return true;
+            }
+            host::LIGHTING_PROGRAM_COMMIT => {
+                d[..2].copy_from_slice(&(self.program.code().len() as u16).to_le_bytes());
+                return true;
+            }
+            host::LIGHTING_FLUSH_TIME => {
+                let (last, max) = self.driver.flush_times();
+                let us = |t: Duration| t.as_micros().min(u16::MAX as u64) as u16;
+                d[..2].copy_from_slice(&us(last).to_le_bytes());
+                d[2..4].copy_from_slice(&us(max).to_le_bytes());
+                return true;
+            }
+            host::LIGHTING_KNOB_REACTION => {
+                let Some(effect) = Effect::from_u8(d[0]) else {
+                    return false;
+                };
+                d[1] = self.config.knob(effect) as u8;
+                return true;
+            }
+            _ => {}
+        }
+
+        let Some(theme) = self.config.themes.get(d[0] as usize) else {
+            return false;
+        };
+        match id {
+            host::LIGHTING_THEME_EFFECT => d[1] = theme.effect as u8,
+            host::LIGHTING_THEME_COLOR => d[1..4].copy_from_slice(&[theme.color.h, theme.color.s, theme.color.v]),
+            host::LIGHTING_THEME_SPEED => d[1] = theme.speed,
+            host::LIGHTING_THEME_KEY_COLOR => {
+                let Some(key) = theme.keys.get(d[1] as usize) else {
+                    return false;
+                };
+                d[2..5].copy_from_slice(&[key.h, key.s, key.v]);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    async fn handle_host(&mut self, mut report: Report) {
+        let handled = match host::command(&report) {
+            host::CUSTOM_SET_VALUE if host::value_id(&report) == host::LIGHTING_PROGRAM_COMMIT => {
+                self.commit_program(&report).await
+            }
+            host::CUSTOM_SET_VALUE => self.set_value(&report),
+            host::CUSTOM_GET_VALUE => self.get_value(&mut report),
+            host::CUSTOM_SAVE => self.save().await.is_ok(),
+            _ => false,
+        };
+
+        if handled {
+            HOST_REPLIES.send(report).await;
+        } else {
+            host::reply_unhandled(report).await;
+        }
+    }
+}
+
+/// Note a key of `chord` going up or down. Returns `true` when this press
+/// completes the chord, which then starts over.
+fn chord_completed(held: &mut [bool], chord: &[(u8, u8)], key: (u8, u8), pressed: bool) -> bool {
+    let Some(i) = chord.iter().position(|&k| k == key) else {
+        return false;
+    };
+    held[i] = pressed;
+    if pressed && held.iter().all(|&h| h) {
+        held.fill(false);
+        return true;
+    }
+    false
+}
+
+impl<I: I2c, F: NorFlash, const DRIVER_COUNT: usize> Controller for BacklightController<I, F, DRIVER_COUNT> {
+    type Event = LightingEvent;
+
+    async fn next_message(&mut self) -> Self::Event {
+        match select3(self.sub.next_message_pure(), LIGHTING_REQUESTS.receive(), GRABBED_EVENTS.receive()).await {
+            Either3::First(event) => LightingEvent::Controller(event),
+            Either3::Second(report) => LightingEvent::Host(report),
+            Either3::Third(event) => LightingEvent::Input(event),
+        }
+    }
+
+    async fn process_event(&mut self, event: Self::Event) {
+        match event {
+            LightingEvent::Controller(ControllerEvent::Layer(layer)) => self.set_layer(layer),
+            LightingEvent::Controller(ControllerEvent::Key(event, _)) => {
+                self.wake();
+                self.record_hit(event);
+                self.follow_knob(event);
+                self.track_chord(event);
+            }
+            LightingEvent::Controller(_) => {}
+            LightingEvent::Host(report) => self.handle_host(report).await,
+            LightingEvent::Input(event) => self.handle_input(event).await,
+        }
+    }
+}
+
+impl<I: I2c, F: NorFlash, const DRIVER_COUNT: usize> PollingController for BacklightController<I, F, DRIVER_COUNT> {
+    const INTERVAL: Duration = Duration::from_millis(33);
+
+    async fn update(&mut self) {
+        let now_ms = self.now_ms();
+        self.draw(now_ms).await;
+
+        if self.transition.as_ref().is_some_and(|t| t.done(now_ms)) {
+            self.transition = None;
+        }
+        if !self.idle
+            && self.config.idle_minutes > 0
+            && matches!(self.mode, Mode::Themes)
+            && self.last_input.elapsed() >= Duration::from_secs(self.config.idle_minutes as u64 * 60)
+        {
+            self.idle = true;
+            self.begin_transition();
+        }
+        if self.notification.as_ref().is_some_and(|n| n.expired(now_ms)) {
+            self.notification = None;
+        }
+
+        if self.last_health_check.elapsed() >= HEALTH_CHECK_INTERVAL {
+            self.last_health_check = Instant::now();
+            if let Err(e) = self.driver.recover().await {
+                defmt::warn!("LED driver recovery failed: {}", defmt::Debug2Format(&e));
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{led_mappings::iso_knob::KNOB_POSITION, lighting::color::Hsv};
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Effect {
+    Off = 0,
+    Solid = 1,
+    Breathing = 2,
+    CycleAll = 3,
+    PerKey = 4,
+    /// Hue wave travelling left to right.
+    WaveHorizontal = 5,
+    /// Hue wave travelling top to bottom.
+    WaveVertical = 6,
+    /// Hue rings moving outwards from the knob.
+    Radial = 7,
+    /// Hue spiral turning around the knob.
+    Spiral = 8,
+    /// Hue by angle around the knob, rotating.
+    Pinwheel = 9,
+    /// Uploaded bytecode program, see `lighting::bytecode`.
+    Custom = 10,
+}
+
+impl Effect {
+    /// Number of effects, the highest discriminant plus one.
+    pub const COUNT: usize = 11;
+
+    pub const fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::Off),
+            1 => Some(Self::Solid),
+            2 => Some(Self::Breathing),
+            3 => Some(Self::CycleAll),
+            4 => Some(Self::PerKey),
+            5 => Some(Self::WaveHorizontal),
+            6 => Some(Self::WaveVertical),
+            7 => Some(Self::Radial),
+            8 => Some(Self::Spiral),
+            9 => Some(Self::Pinwheel),
+            10 => Some(Self::Custom),
+            _ => None,
+        }
+    }
+}
+
+/// Animation phase in 0..=255, wrapping. A speed of 128 runs one cycle in
+/// roughly two seconds.
+#[inline]
+pub const fn phase(now_ms: u32, speed: u8) -> u8 { (((now_ms >> 2).wrapping_mul(speed as u32 + 1)) >> 8) as u8 }
+
+/// Triangle wave over a phase: 0 -> 255 -> 0.
+#[inline]
+pub const fn triangle(phase: u8) -> u8 {
+    match phase {
+        0..=127 => phase * 2,
+        _ => (255 - phase) * 2,
+    }
+}
+
+/// Angle of `(dx, dy)` as a fraction of a full turn, 0..=255.
+///
+/// Uses `atan(r) ~ r * (pi/4 + 0.273 * (1 - r))` on the first octant, which
+/// stays within about one step of the exact angle.
+pub const fn angle(dx: i32, dy: i32) -> u8 {
+    let (ax, ay) = (dx.unsigned_abs(), dy.unsigned_abs());
+    if ax == 0 && ay == 0 {
+        return 0;
+    }
+
+    let (small, big) = if ax < ay { (ax, ay) } else { (ay, ax) };
+    let r = small * 256 / big;
+    let mut a = (32 * r + 11 * r * (256 - r) / 256) / 256;
+    if ay > ax {
+        a = 64 - a;
+    }
+    if dx < 0 {
+        a = 128 - a;
+    }
+    if dy < 0 {
+        a = 256 - a;
+    }
+    a as u8
+}
+
+/// Distance between key centers in physical key units.
+#[inline]
+pub const fn distance(dx: i32, dy: i32) -> u32 { (dx * dx + dy * dy).unsigned_abs().isqrt() }
+
+/// Color of one LED for effects that only depend on the base color, the key
+/// position and time. [`Effect::PerKey`] is resolved by the theme, which owns
+/// the key colors, and [`Effect::Custom`] by the backlight controller, which
+/// owns the program.
+pub const fn render(effect: Effect, color: Hsv, speed: u8, pos: (u8, u8), now_ms: u32) -> Hsv {
+    let p = phase(now_ms, speed);
+    let dx = pos.0 as i32 - KNOB_POSITION.0 as i32;
+    let dy = pos.1 as i32 - KNOB_POSITION.1 as i32;
+
+    let hue_shift = match effect {
+        Effect::Off => return Hsv::new(0, 0, 0),
+        Effect::Solid | Effect::PerKey | Effect::Custom => return color,
+        Effect::Breathing => return color.dimmed(triangle(p)),
+        Effect::CycleAll => p,
+        Effect::WaveHorizontal => pos.0.wrapping_sub(p),
+        Effect::WaveVertical => (pos.1 * 2).wrapping_sub(p),
+        Effect::Radial => (distance(dx, dy) as u8).wrapping_sub(p),
+        Effect::Spiral => angle(dx, dy).wrapping_add(distance(dx, dy) as u8).wrapping_sub(p),
+        Effect::Pinwheel => angle(dx, dy).wrapping_add(p),
+    };
+    Hsv { h: color.h.wrapping_add(hue_shift), ..color }
+}
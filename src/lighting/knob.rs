@@ -0,0 +1,141 @@
+use crate::{
+    led_mappings::iso_knob::{LED_MATRIX, LED_POSITION},
+    lighting::color::Rgb,
+};
+
+/// Matrix row and number of keys the level bar is drawn on: the number row
+/// from the grave key to Backspace.
+const BAR_ROW: u8 = 1;
+const BAR_KEYS: u8 = 14;
+/// Detents the level bar spans, two per key.
+const LEVEL_STEPS: u8 = BAR_KEYS * 2;
+/// How long a reaction stays up after the last detent, the last
+/// [`FADE_MS`] of it fading out.
+const SHOW_MS: u32 = 1500;
+const FADE_MS: u32 = 500;
+/// Sweep band half width and movement per detent, in `LED_POSITION` units
+/// (14 per key).
+const SWEEP_HALF_WIDTH: i16 = 21;
+const SWEEP_STEP: i16 = 14;
+const SWEEP_MAX_X: i16 = 227;
+/// Hue change per detent for [`KnobReaction::HueStep`].
+const HUE_STEP: u8 = 8;
+
+/// How the backlight reacts to the knob, chosen per effect.
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum KnobReaction {
+    None = 0,
+    /// White band over the effect that moves one key per detent, in the
+    /// turning direction.
+    Sweep = 1,
+    /// Number row as a bar following the volume steps sent by the knob.
+    LevelBar = 2,
+    /// Each detent rotates the hue of the active theme while it is shown;
+    /// the saved theme keeps its colors.
+    HueStep = 3,
+}
+
+impl KnobReaction {
+    /// Number of reactions, the highest discriminant plus one.
+    pub const COUNT: usize = 4;
+
+    pub const fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::None),
+            1 => Some(Self::Sweep),
+            2 => Some(Self::LevelBar),
+            3 => Some(Self::HueStep),
+            _ => None,
+        }
+    }
+}
+
+/// Knob state the reactions are drawn from.
+pub struct Knob {
+    /// Clockwise minus counter-clockwise detents, clamped to
+    /// `0..=LEVEL_STEPS`. The host's actual volume is unknown, so the bar
+    /// starts half full and only follows the steps sent since boot.
+    level: u8,
+    sweep_x: i16,
+    /// Hue added to the theme's colors by [`KnobReaction::HueStep`].
+    hue: u8,
+    last_turn_ms: Option<u32>,
+}
+
+impl Default for Knob {
+    fn default() -> Self { Self { level: LEVEL_STEPS / 2, sweep_x: SWEEP_MAX_X / 2, hue: 0, last_turn_ms: None } }
+}
+
+impl Knob {
+    /// Follow one detent. The hue only turns under `HueStep`, so other
+    /// effects do not pick up a hidden offset.
+    pub fn turn(&mut self, reaction: KnobReaction, clockwise: bool, now_ms: u32) {
+        if reaction == KnobReaction::HueStep {
+            self.hue = if clockwise { self.hue.wrapping_add(HUE_STEP) } else { self.hue.wrapping_sub(HUE_STEP) };
+        }
+        if clockwise {
+            self.level = (self.level + 1).min(LEVEL_STEPS);
+            self.sweep_x = (self.sweep_x + SWEEP_STEP).min(SWEEP_MAX_X);
+        } else {
+            self.level = self.level.saturating_sub(1);
+            self.sweep_x = (self.sweep_x - SWEEP_STEP).max(0);
+        }
+        self.last_turn_ms = Some(now_ms);
+    }
+
+    /// Hue to add to the theme's colors while rendering `reaction`.
+    #[inline]
+    pub const fn hue_shift(&self, reaction: KnobReaction) -> u8 {
+        match reaction {
+            KnobReaction::HueStep => self.hue,
+            _ => 0,
+        }
+    }
+
+    /// 255 right after a detent, fading to 0 once the reaction is over.
+    fn strength(&self, now_ms: u32) -> u8 {
+        let Some(turned_ms) = self.last_turn_ms else {
+            return 0;
+        };
+        match now_ms.wrapping_sub(turned_ms) {
+            t if t < SHOW_MS - FADE_MS => 255,
+            t if t < SHOW_MS => ((SHOW_MS - t) * 255 / FADE_MS) as u8,
+            _ => 0,
+        }
+    }
+
+    /// Draw `reaction` over the effect's color of one LED.
+    /// [`KnobReaction::HueStep`] draws nothing here, see
+    /// [`hue_shift`](Self::hue_shift).
+    pub fn render(&self, reaction: KnobReaction, led_index: usize, now_ms: u32, base: Rgb) -> Rgb {
+        let strength = self.strength(now_ms);
+        if strength == 0 {
+            return base;
+        }
+
+        match reaction {
+            KnobReaction::Sweep => {
+                let distance = (LED_POSITION[led_index].0 as i16 - self.sweep_x).abs();
+                if distance >= SWEEP_HALF_WIDTH {
+                    return base;
+                }
+                let amount = (SWEEP_HALF_WIDTH - distance) as u32 * strength as u32 / SWEEP_HALF_WIDTH as u32;
+                base.blend(Rgb::WHITE, amount as u8)
+            }
+            KnobReaction::LevelBar => {
+                let (row, col) = LED_MATRIX[led_index];
+                if row != BAR_ROW || col >= BAR_KEYS {
+                    return base;
+                }
+                let v = match self.level.saturating_sub(col * 2) {
+                    0 => 0,
+                    1 => 64,
+                    _ => 255,
+                };
+                base.blend(Rgb::new(v, v, v), strength)
+            }
+            KnobReaction::None | KnobReaction::HueStep => base,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{
+    led_mappings::iso_knob::LED_COUNT,
+    lighting::{
+        color::{Hsv, Rgb},
+        effect::{phase, triangle},
+    },
+};
+
+/// Bytes of the LED bitmask in a notification request.
+pub const MASK_LEN: usize = LED_COUNT.div_ceil(8);
+/// Encoded size of a notification request.
+pub const NOTIFICATION_LEN: usize = 6 + MASK_LEN;
+
+const BLINK_MS: u32 = 250;
+const PULSE_SPEED: u8 = 255;
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Solid = 0,
+    Blink = 1,
+    Pulse = 2,
+}
+
+impl Pattern {
+    pub const fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::Solid),
+            1 => Some(Self::Blink),
+            2 => Some(Self::Pulse),
+            _ => None,
+        }
+    }
+}
+
+/// Host notification drawn over the normal lighting for a limited time.
+///
+/// Encoded as `[h, s, v, pattern, duration: u16 (100 ms units), mask...]`,
+/// where `mask` has one bit per LED in `LED_LAYOUT` order, LSB first. An
+/// all-zero mask covers the whole board.
+pub struct Notification {
+    color: Hsv,
+    pattern: Pattern,
+    mask: [u8; MASK_LEN],
+    started_ms: u32,
+    duration_ms: u32,
+}
+
+impl Notification {
+    /// Decode a notification request. A zero duration gives a notification
+    /// that has already expired, which clears a running one.
+    pub fn decode(data: &[u8; NOTIFICATION_LEN], now_ms: u32) -> Option<Self> {
+        let pattern = Pattern::from_u8(data[3])?;
+        let duration_ms = u16::from_le_bytes([data[4], data[5]]) as u32 * 100;
+
+        let mut mask = [0u8; MASK_LEN];
+        mask.copy_from_slice(&data[6..]);
+        if mask.iter().all(|&b| b == 0) {
+            mask.fill(0xFF);
+        }
+
+        Some(Self { color: Hsv::new(data[0], data[1], data[2]), pattern, mask, started_ms: now_ms, duration_ms })
+    }
+
+    #[inline]
+    pub const fn expired(&self, now_ms: u32) -> bool { now_ms.wrapping_sub(self.started_ms) >= self.duration_ms }
+
+    #[inline]
+    fn covers(&self, led_index: usize) -> bool {
+        self.mask.get(led_index / 8).is_some_and(|b| b & (1 << (led_index % 8)) != 0)
+    }
+
+    /// Color of `led_index` with the notification drawn over `base`, the
+    /// normal lighting, which shows through where the notification does not
+    /// cover the LED or has expired.
+    pub fn over(&self, led_index: usize, now_ms: u32, base: Rgb) -> Rgb {
+        self.render(led_index, now_ms).unwrap_or(base)
+    }
+
+    fn render(&self, led_index: usize, now_ms: u32) -> Option<Rgb> {
+        if !self.covers(led_index) || self.expired(now_ms) {
+            return None;
+        }
+
+        let t = now_ms.wrapping_sub(self.started_ms);
+        let color = match self.pattern {
+            Pattern::Solid => self.color,
+            Pattern::Blink if (t / BLINK_MS) % 2 == 1 => Hsv::new(0, 0, 0),
+            Pattern::Blink => self.color,
+            Pattern::Pulse => self.color.dimmed(triangle(phase(t, PULSE_SPEED))),
+        };
+        Some(color.to_rgb())
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    led_mappings::iso_knob::{LED_COUNT, led_at_matrix},
+    lighting::{
+        color::{Hsv, Rgb},
+        setup::Exit,
+    },
+};
+
+/// Keys held together to start painting: Left Ctrl + Right Ctrl.
+pub const PAINT_CHORD: [(u8, u8); 2] = [(5, 0), (5, 12)];
+/// Knob press, switching the knob between hue and saturation. It has an LED
+/// of its own, and this layout has no key without one to use instead.
+pub const KNOB_PRESS: (u8, u8) = (0, 15);
+/// End painting when no key is selected: Esc keeps the colors, Backspace
+/// throws them away.
+const SAVE_KEY: (u8, u8) = (0, 0);
+const DISCARD_KEY: (u8, u8) = (1, 13);
+
+const STEP: u8 = 8;
+const BLINK_MS: u32 = 250;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Target {
+    Hue,
+    Saturation,
+}
+
+/// On-keyboard per-key color editor.
+///
+/// Tap a key to select it, turn the knob to change its hue, press the knob to
+/// switch to saturation and back, and tap the key again to deselect it. With
+/// no key selected, Esc ends painting and keeps the colors, Backspace ends it
+/// and leaves the theme as it was; to paint either key itself, select it
+/// while another key is selected. Likewise the knob press with no key selected
+/// selects the knob's own LED, since with a key selected it switches the
+/// target.
+pub struct PaintMode {
+    keys: [Hsv; LED_COUNT],
+    selected: Option<usize>,
+    target: Target,
+}
+
+impl PaintMode {
+    pub const fn new(keys: [Hsv; LED_COUNT]) -> Self { Self { keys, selected: None, target: Target::Hue } }
+
+    pub const fn keys(&self) -> &[Hsv; LED_COUNT] { &self.keys }
+
+    /// Handle a key press, returning how painting ends if it does.
+    pub fn press(&mut self, row: u8, col: u8) -> Option<Exit> {
+        if (row, col) == KNOB_PRESS && self.selected.is_some() {
+            self.target = match self.target {
+                Target::Hue => Target::Saturation,
+                Target::Saturation => Target::Hue,
+            };
+            return None;
+        }
+        if self.selected.is_none() {
+            match (row, col) {
+                SAVE_KEY => return Some(Exit::Save),
+                DISCARD_KEY => return Some(Exit::Discard),
+                _ => {}
+            }
+        }
+
+        if let Some(led_index) = led_at_matrix(row, col) {
+            self.selected = if self.selected == Some(led_index) { None } else { Some(led_index) };
+        }
+        None
+    }
+
+    /// Handle one knob detent on the selected key.
+    pub fn turn(&mut self, clockwise: bool) {
+        let Some(key) = self.selected.and_then(|led_index| self.keys.get_mut(led_index)) else {
+            return;
+        };
+
+        match (self.target, clockwise) {
+            (Target::Hue, true) => key.h = key.h.wrapping_add(STEP),
+            (Target::Hue, false) => key.h = key.h.wrapping_sub(STEP),
+            (Target::Saturation, true) => key.s = key.s.saturating_add(STEP),
+            (Target::Saturation, false) => key.s = key.s.saturating_sub(STEP),
+        }
+        // A key that was off becomes visible as soon as it is painted.
+        if key.v == 0 {
+            key.v = 255;
+        }
+    }
+
+    pub fn render(&self, led_index: usize, now_ms: u32) -> Rgb {
+        if self.selected == Some(led_index) && (now_ms / BLINK_MS) % 2 == 1 {
+            return Rgb::BLACK;
+        }
+        self.keys.get(led_index).map_or(Rgb::BLACK, |key| key.to_rgb())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{
+    led_mappings::iso_knob::LED_MATRIX,
+    lighting::{
+        color::Rgb,
+        effect::Effect,
+        knob::KnobReaction,
+        theme::{LightingConfig, MAX_IDLE_MINUTES, MAX_TRANSITION},
+    },
+};
+
+/// Keys held together to start lighting setup: Left Alt + Right Alt.
+pub const SETUP_CHORD: [(u8, u8); 2] = [(5, 2), (5, 10)];
+/// Knob press, moving on to the next setting.
+const NEXT_KEY: (u8, u8) = (0, 15);
+const SAVE_KEY: (u8, u8) = (0, 0);
+const DISCARD_KEY: (u8, u8) = (1, 13);
+/// Row of the number keys; key `1` picks the first setting.
+const FIELD_ROW: u8 = 1;
+
+const HUE_STEP: u8 = 8;
+const STEP: u8 = 16;
+const BRIGHTNESS_STEP: u8 = 10;
+/// 50 ms per detent.
+const TRANSITION_STEP: u8 = 5;
+const BLINK_MS: u32 = 500;
+
+/// Setting the knob changes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Field {
+    Effect,
+    Hue,
+    Saturation,
+    Speed,
+    Brightness,
+    /// Knob reaction of the theme's effect.
+    Knob,
+    /// Crossfade time.
+    Transition,
+    /// Minutes without input before the backlight fades out.
+    Idle,
+}
+
+impl Field {
+    const ALL: [Self; 8] = [
+        Self::Effect,
+        Self::Hue,
+        Self::Saturation,
+        Self::Speed,
+        Self::Brightness,
+        Self::Knob,
+        Self::Transition,
+        Self::Idle,
+    ];
+}
+
+/// How a lighting mode was left.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Exit {
+    Save,
+    Discard,
+}
+
+/// On-keyboard lighting setup of the active layer's theme.
+///
+/// The number keys pick a setting, lit white over the theme with the picked
+/// one blinking, and the knob press moves on to the next. Turning the knob
+/// changes the setting, shown live. Esc saves and leaves, Backspace restores
+/// the settings from before and leaves.
+pub struct SetupMode {
+    field: usize,
+    saved: LightingConfig,
+}
+
+impl SetupMode {
+    pub const fn new(config: LightingConfig) -> Self { Self { field: 0, saved: config } }
+
+    /// Settings from when setup started, put back on [`Exit::Discard`].
+    pub const fn saved(&self) -> &LightingConfig { &self.saved }
+
+    pub const fn field(&self) -> Field { Field::ALL[self.field] }
+
+    /// Handle a key press, returning how setup ends if it does.
+    pub fn press(&mut self, row: u8, col: u8) -> Option<Exit> {
+        match (row, col) {
+            SAVE_KEY => return Some(Exit::Save),
+            DISCARD_KEY => return Some(Exit::Discard),
+            NEXT_KEY => self.field = (self.field + 1) % Field::ALL.len(),
+            (FIELD_ROW, col) if (1..=Field::ALL.len()).contains(&(col as usize)) => self.field = col as usize - 1,
+            _ => {}
+        }
+        None
+    }
+
+    /// Handle one knob detent on the theme of `layer`.
+    pub fn turn(&self, config: &mut LightingConfig, layer: u8, clockwise: bool) {
+        let step = |v: u8, by: u8| if clockwise { v.saturating_add(by) } else { v.saturating_sub(by) };
+        match self.field() {
+            Field::Brightness => {
+                config.brightness = match clockwise {
+                    true => (config.brightness + BRIGHTNESS_STEP).min(100),
+                    false => config.brightness.saturating_sub(BRIGHTNESS_STEP),
+                };
+                return;
+            }
+            Field::Knob => {
+                let reaction = &mut config.knob[config.theme(layer).effect as usize];
+                let count = KnobReaction::COUNT as u8;
+                let next = if clockwise { *reaction as u8 + 1 } else { *reaction as u8 + count - 1 };
+                *reaction = KnobReaction::from_u8(next % count).unwrap_or(*reaction);
+                return;
+            }
+            Field::Transition => {
+                config.transition = step(config.transition, TRANSITION_STEP).min(MAX_TRANSITION);
+                return;
+            }
+            Field::Idle => {
+                config.idle_minutes = step(config.idle_minutes, 1).min(MAX_IDLE_MINUTES);
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(theme) = config.theme_mut(layer) else {
+            return;
+        };
+        match self.field() {
+            Field::Effect => {
+                let count = Effect::COUNT as u8;
+                let next = if clockwise { theme.effect as u8 + 1 } else { theme.effect as u8 + count - 1 };
+                theme.effect = Effect::from_u8(next % count).unwrap_or(theme.effect);
+            }
+            Field::Hue if clockwise => theme.color.h = theme.color.h.wrapping_add(HUE_STEP),
+            Field::Hue => theme.color.h = theme.color.h.wrapping_sub(HUE_STEP),
+            Field::Saturation => theme.color.s = step(theme.color.s, STEP),
+            Field::Speed => theme.speed = step(theme.speed, STEP),
+            Field::Brightness | Field::Knob | Field::Transition | Field::Idle => {}
+        }
+    }
+
+    /// Draw the setting markers over the theme's color of one LED.
+    pub fn render(&self, led_index: usize, now_ms: u32, base: Rgb) -> Rgb {
+        let (row, col) = LED_MATRIX[led_index];
+        if row != FIELD_ROW || !(1..=Field::ALL.len()).contains(&(col as usize)) {
+            return base;
+        }
+        if col as usize - 1 == self.field && (now_ms / BLINK_MS) % 2 == 1 {
+            return Rgb::BLACK;
+        }
+        Rgb::WHITE
+    }
+}
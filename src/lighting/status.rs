@@ -0,0 +1,37 @@
+use crate::{led_mappings::iso_knob::LED_POSITION, lighting::color::Rgb};
+
+const SWEEP_WIDTH: i32 = 40;
+/// Sweep travel: from fully left of the board to fully right of it.
+const SWEEP_SPAN: i32 = 228 + 2 * SWEEP_WIDTH;
+const SWEEP_MS: u32 = 700;
+
+/// Short animations giving feedback on system events. They are drawn over
+/// every other lighting mode while they run.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum StatusAnimation {
+    /// White band sweeping across the board once the LED drivers are up.
+    Boot,
+}
+
+impl StatusAnimation {
+    pub const fn duration_ms(self) -> u32 {
+        match self {
+            Self::Boot => SWEEP_MS,
+        }
+    }
+
+    pub fn render(self, led_index: usize, elapsed_ms: u32) -> Rgb {
+        match self {
+            Self::Boot => {
+                let x = LED_POSITION.get(led_index).map_or(0, |pos| pos.0 as i32);
+                let center = (elapsed_ms.min(SWEEP_MS) * SWEEP_SPAN as u32 / SWEEP_MS) as i32 - SWEEP_WIDTH;
+                let offset = (x - center).abs();
+                if offset >= SWEEP_WIDTH {
+                    return Rgb::BLACK;
+                }
+                let v = (255 - offset * 255 / SWEEP_WIDTH) as u8;
+                Rgb::new(v, v, v)
+            }
+        }
+    }
+}
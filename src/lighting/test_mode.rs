@@ -0,0 +1,63 @@
+use crate::{
+    led_mappings::iso_knob::{LED_COUNT, LED_MATRIX},
+    lighting::color::Rgb,
+};
+
+const COLORS: [Rgb; 4] = [Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 255), Rgb::new(255, 255, 255)];
+const COLOR_STEP_MS: u32 = 1000;
+const WALK_STEP_MS: u32 = 150;
+const COLORS_MS: u32 = COLORS.len() as u32 * COLOR_STEP_MS;
+const CYCLE_MS: u32 = COLORS_MS + LED_COUNT as u32 * WALK_STEP_MS;
+
+/// Backlight factory test.
+///
+/// Runs all LEDs through red, green, blue and white, then lights them one by
+/// one in `LED_LAYOUT` order, and repeats. Pressing any key or turning the
+/// knob stops the cycle and steps through the LEDs one at a time: a key or a
+/// clockwise detent moves to the next LED, a counter-clockwise detent to the
+/// previous one.
+pub struct TestMode {
+    started_ms: u32,
+    manual: Option<usize>,
+}
+
+impl TestMode {
+    pub const fn new(now_ms: u32) -> Self { Self { started_ms: now_ms, manual: None } }
+
+    /// LED lit on its own right now, if any.
+    pub fn current_led(&self, now_ms: u32) -> Option<usize> {
+        if self.manual.is_some() {
+            return self.manual;
+        }
+
+        let t = now_ms.wrapping_sub(self.started_ms) % CYCLE_MS;
+        t.checked_sub(COLORS_MS).map(|walk| (walk / WALK_STEP_MS) as usize)
+    }
+
+    pub fn render(&self, led_index: usize, now_ms: u32) -> Rgb {
+        match self.current_led(now_ms) {
+            Some(current) if current == led_index => Rgb::new(255, 255, 255),
+            Some(_) => Rgb::BLACK,
+            None => {
+                let t = now_ms.wrapping_sub(self.started_ms) % CYCLE_MS;
+                COLORS[(t / COLOR_STEP_MS) as usize]
+            }
+        }
+    }
+
+    /// Report of the LED under test for the host: `[1, led, row, col]`, with
+    /// `0xFF` for all three while every LED is lit.
+    pub fn status(&self, now_ms: u32) -> [u8; 4] {
+        let led = self.current_led(now_ms);
+        let (row, col) = led.map_or((0xFF, 0xFF), |led| LED_MATRIX[led]);
+        [1, led.map_or(0xFF, |led| led as u8), row, col]
+    }
+
+    /// Move the lit LED forward or backward in `LED_LAYOUT` order, starting
+    /// from the one the automatic walk was on.
+    pub fn step(&mut self, forward: bool, now_ms: u32) {
+        let current = self.current_led(now_ms).unwrap_or(LED_COUNT - 1);
+        let next = if forward { (current + 1) % LED_COUNT } else { (current + LED_COUNT - 1) % LED_COUNT };
+        self.manual = Some(next);
+    }
+}
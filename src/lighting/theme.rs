@@ -0,0 +1,159 @@
+use crate::{
+    keymap::NUM_LAYER,
+    led_mappings::iso_knob::{LED_COUNT, LED_POSITION},
+    lighting::{
+        color::{Hsv, Rgb},
+        effect::{self, Effect},
+        knob::KnobReaction,
+    },
+};
+
+/// Lighting shown while a layer is the active one.
+#[derive(Copy, Clone)]
+pub struct Theme {
+    pub effect: Effect,
+    pub color: Hsv,
+    pub speed: u8,
+    /// Static colors, indexed like `LED_LAYOUT`, used by [`Effect::PerKey`].
+    pub keys: [Hsv; LED_COUNT],
+}
+
+impl Theme {
+    pub const ENCODED_LEN: usize = 5 + LED_COUNT * 3;
+
+    pub const fn new(effect: Effect, color: Hsv, speed: u8) -> Self {
+        Self { effect, color, speed, keys: [color; LED_COUNT] }
+    }
+
+    #[inline]
+    pub fn render(&self, led_index: usize, now_ms: u32) -> Rgb { self.render_shifted(led_index, now_ms, 0) }
+
+    /// [`render`](Self::render) with `hue_shift` added to the hue of the
+    /// theme's colors.
+    #[inline]
+    pub fn render_shifted(&self, led_index: usize, now_ms: u32, hue_shift: u8) -> Rgb {
+        let mut base = match self.effect {
+            Effect::PerKey => self.keys.get(led_index).copied().unwrap_or(self.color),
+            _ => self.color,
+        };
+        base.h = base.h.wrapping_add(hue_shift);
+        let pos = LED_POSITION.get(led_index).copied().unwrap_or_default();
+        effect::render(self.effect, base, self.speed, pos, now_ms).to_rgb()
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.effect as u8;
+        out[1..4].copy_from_slice(&[self.color.h, self.color.s, self.color.v]);
+        out[4] = self.speed;
+        for (dst, key) in out[5..Self::ENCODED_LEN].chunks_exact_mut(3).zip(self.keys.iter()) {
+            dst.copy_from_slice(&[key.h, key.s, key.v]);
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let effect = Effect::from_u8(bytes[0])?;
+        let mut theme = Self::new(effect, Hsv::new(bytes[1], bytes[2], bytes[3]), bytes[4]);
+        for (key, src) in theme.keys.iter_mut().zip(bytes[5..Self::ENCODED_LEN].chunks_exact(3)) {
+            *key = Hsv::new(src[0], src[1], src[2]);
+        }
+        Some(theme)
+    }
+}
+
+/// Knob reaction of each effect until one is set. The knob sends volume
+/// steps, so most effects show the level bar; the hue waves show the sweep,
+/// which stands out from them better, and breathing steps its hue.
+const DEFAULT_KNOB: [KnobReaction; Effect::COUNT] = [
+    KnobReaction::None,     // Off
+    KnobReaction::LevelBar, // Solid
+    KnobReaction::HueStep,  // Breathing
+    KnobReaction::LevelBar, // CycleAll
+    KnobReaction::LevelBar, // PerKey
+    KnobReaction::Sweep,    // WaveHorizontal
+    KnobReaction::Sweep,    // WaveVertical
+    KnobReaction::Sweep,    // Radial
+    KnobReaction::Sweep,    // Spiral
+    KnobReaction::Sweep,    // Pinwheel
+    KnobReaction::LevelBar, // Custom
+];
+
+/// Longest crossfade, in 10 ms units.
+pub const MAX_TRANSITION: u8 = 200;
+/// Longest idle time before the backlight fades out, in minutes.
+pub const MAX_IDLE_MINUTES: u8 = 60;
+
+/// Persisted lighting state: one theme per keymap layer, the knob reaction
+/// of each effect, the global brightness and the fade timings.
+#[derive(Copy, Clone)]
+pub struct LightingConfig {
+    pub brightness: u8,
+    pub themes: [Theme; NUM_LAYER],
+    /// Indexed by `Effect as usize`.
+    pub knob: [KnobReaction; Effect::COUNT],
+    /// Crossfade time between lighting changes, in 10 ms units.
+    pub transition: u8,
+    /// Minutes without input before the backlight fades out, 0 for never.
+    pub idle_minutes: u8,
+}
+
+impl LightingConfig {
+    pub const ENCODED_LEN: usize = 3 + Theme::ENCODED_LEN * NUM_LAYER + Effect::COUNT;
+    /// Bump whenever the encoding changes so stale records are ignored.
+    pub const VERSION: u8 = 5;
+
+    #[inline]
+    pub fn theme(&self, layer: u8) -> &Theme { self.themes.get(layer as usize).unwrap_or(&self.themes[0]) }
+
+    #[inline]
+    pub fn theme_mut(&mut self, layer: u8) -> Option<&mut Theme> { self.themes.get_mut(layer as usize) }
+
+    #[inline]
+    pub fn knob(&self, effect: Effect) -> KnobReaction { self.knob[effect as usize] }
+
+    #[inline]
+    pub fn transition_ms(&self) -> u32 { self.transition as u32 * 10 }
+
+    pub fn encode(&self, out: &mut [u8; Self::ENCODED_LEN]) {
+        out[0] = self.brightness;
+        out[1] = self.transition;
+        out[2] = self.idle_minutes;
+        let (themes, knob) = out[3..].split_at_mut(Theme::ENCODED_LEN * NUM_LAYER);
+        for (dst, theme) in themes.chunks_exact_mut(Theme::ENCODED_LEN).zip(self.themes.iter()) {
+            theme.encode(dst);
+        }
+        for (dst, reaction) in knob.iter_mut().zip(self.knob.iter()) {
+            *dst = *reaction as u8;
+        }
+    }
+
+    pub fn decode(bytes: &[u8; Self::ENCODED_LEN]) -> Option<Self> {
+        let (themes_src, knob_src) = bytes[3..].split_at(Theme::ENCODED_LEN * NUM_LAYER);
+        let mut themes = Self::default().themes;
+        for (theme, src) in themes.iter_mut().zip(themes_src.chunks_exact(Theme::ENCODED_LEN)) {
+            *theme = Theme::decode(src)?;
+        }
+        let mut knob = [KnobReaction::None; Effect::COUNT];
+        for (reaction, &src) in knob.iter_mut().zip(knob_src.iter()) {
+            *reaction = KnobReaction::from_u8(src)?;
+        }
+        Some(Self {
+            brightness: bytes[0].min(100),
+            themes,
+            knob,
+            transition: bytes[1].min(MAX_TRANSITION),
+            idle_minutes: bytes[2].min(MAX_IDLE_MINUTES),
+        })
+    }
+}
+
+impl Default for LightingConfig {
+    fn default() -> Self {
+        Self {
+            brightness: 100,
+            themes: [Theme::new(Effect::Solid, Hsv::WHITE, 128); NUM_LAYER],
+            knob: DEFAULT_KNOB,
+            transition: 30,
+            idle_minutes: 10,
+        }
+    }
+}
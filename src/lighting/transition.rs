@@ -0,0 +1,27 @@
+use crate::{led_mappings::iso_knob::LED_COUNT, lighting::color::Rgb};
+
+/// Crossfade from the frame that was on the LEDs when it started to whatever
+/// is rendered since.
+pub struct Transition {
+    from: [Rgb; LED_COUNT],
+    started_ms: u32,
+    duration_ms: u32,
+}
+
+impl Transition {
+    /// Start fading out of `shown`. There is nothing to fade with a zero
+    /// duration.
+    pub fn start(shown: &[Rgb; LED_COUNT], now_ms: u32, duration_ms: u32) -> Option<Self> {
+        (duration_ms > 0).then_some(Self { from: *shown, started_ms: now_ms, duration_ms })
+    }
+
+    #[inline]
+    pub fn done(&self, now_ms: u32) -> bool { now_ms.wrapping_sub(self.started_ms) >= self.duration_ms }
+
+    /// Color of one LED with `to` faded in by the time passed.
+    #[inline]
+    pub fn blend(&self, led_index: usize, now_ms: u32, to: Rgb) -> Rgb {
+        let elapsed = now_ms.wrapping_sub(self.started_ms).min(self.duration_ms);
+        self.from[led_index].blend(to, (elapsed * 255 / self.duration_ms) as u8)
+    }
+}
@@ -2,39 +2,63 @@
 #![no_std]
 
 mod ckled2001;
+mod debounce;
+mod flash_record;
+mod gpio_matrix;
 mod hc595_cols;
+mod host;
+mod input_grab;
+mod key_health;
 mod keymap;
 mod led_mappings;
+mod lighting;
+mod matrix_io;
+mod matrix_settings;
+mod scan_stats;
 mod shiftreg_matrix;
 mod vial;
 
 use crate::{
     ckled2001::driver::Ckled2001,
     hc595_cols::Hc595Cols,
+    input_grab::{self, Grabbable},
+    key_health::Offender,
     led_mappings::iso_knob::LED_LAYOUT,
-    shiftreg_matrix::ShiftRegMatrix,
+    lighting::{controller::BacklightController, status::StatusAnimation},
+    matrix_settings::{self, MatrixSettings},
+    scan_stats,
+    shiftreg_matrix::{KEY_HEALTH, SCAN_STATS, ScanTiming, ShiftRegMatrix},
 };
 use core::panic::PanicInfo;
 use cortex_m::{asm, peripheral::SCB};
+use defmt_rtt as _;
+use embassy_embedded_hal::{adapter::BlockingAsync, flash::partition::Partition, shared_bus::asynch::i2c::I2cDevice};
 use embassy_executor::Spawner;
 use embassy_stm32::{
     Config,
     bind_interrupts,
     exti::{self, ExtiInput},
-    flash::Flash,
+    flash::{Blocking, Flash},
     gpio::{Level, Output, Pull, Speed},
     i2c,
     interrupt::typelevel,
+    mode::Async,
     peripherals::{self, USB},
     rcc::{self},
+    spi::{self, Spi},
     time::Hertz,
     usb::{self, Driver},
 };
-use embassy_time::Timer;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+};
+use embassy_time::{Duration, Timer};
 use rmk::{
     channel::EVENT_CHANNEL,
     config::{BehaviorConfig, DeviceConfig, PositionalConfig, RmkConfig, StorageConfig, VialConfig},
-    futures::future::join3,
+    controller::PollingController,
+    futures::future::{join, join3, join5},
     initialize_encoder_keymap_and_storage,
     input_device::{Runnable, rotary_encoder::RotaryEncoder},
     keyboard::Keyboard,
@@ -42,9 +66,53 @@ use rmk::{
     run_rmk,
     storage::async_flash_wrapper,
 };
+use static_cell::StaticCell;
 use vial::{VIAL_KEYBOARD_DEF, VIAL_KEYBOARD_ID};
 
 const LED_DRIVER_COUNT: usize = 2;
+/// Drive the column shift registers from SPI1 with DMA instead of
+/// bit-banging the same pins.
+const HC595_OVER_SPI: bool = true;
+/// Matrix scan and debounce settings until the host saves others.
+const MATRIX_DEFAULTS: matrix_settings::Defaults = matrix_settings::Defaults {
+    timing: ScanTiming::DEFAULT,
+    algorithm: debounce::Algorithm::SymmetricDefer,
+    debounce_ms: 10,
+    // Set `key_ms[row][col]` to give a chattering switch a longer time.
+    key_ms: [[0; keymap::COL]; keymap::ROW],
+    mask_stuck: false,
+};
+/// How often new chattering or stuck keys and the scan counters are logged.
+const MATRIX_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// Flash layout: rmk keeps the keymap in the last sectors, our own settings
+// records sit directly below it. `memory.x` ends the program's FLASH region
+// at the lowest of these pages.
+const FLASH_SIZE: u32 = 128 * 1024;
+const FLASH_PAGE_SIZE: u32 = 2 * 1024;
+const RMK_STORAGE_SECTORS: u8 = 2;
+const RMK_STORAGE_SIZE: u32 = RMK_STORAGE_SECTORS as u32 * FLASH_PAGE_SIZE;
+const RMK_STORAGE_OFFSET: u32 = FLASH_SIZE - RMK_STORAGE_SIZE;
+/// Lighting themes, then the custom effect program on the next page.
+const LIGHTING_STORAGE_SIZE: u32 = 2 * FLASH_PAGE_SIZE;
+const LIGHTING_STORAGE_OFFSET: u32 = RMK_STORAGE_OFFSET - LIGHTING_STORAGE_SIZE;
+/// Matrix scan settings.
+const MATRIX_STORAGE_SIZE: u32 = FLASH_PAGE_SIZE;
+const MATRIX_STORAGE_OFFSET: u32 = LIGHTING_STORAGE_OFFSET - MATRIX_STORAGE_SIZE;
+/// Length of FLASH in `memory.x`, which must match the partitions above.
+const PROGRAM_FLASH_SIZE: u32 = FLASH_SIZE - 5 * FLASH_PAGE_SIZE;
+const _: () = assert!(MATRIX_STORAGE_OFFSET == PROGRAM_FLASH_SIZE, "update memory.x with the flash layout");
+
+type SharedFlash = Mutex<NoopRawMutex, BlockingAsync<Flash<'static, Blocking>>>;
+type SharedI2c = Mutex<CriticalSectionRawMutex, i2c::I2c<'static, Async, i2c::Master>>;
+
+static FLASH: StaticCell<SharedFlash> = StaticCell::new();
+/// I2C1 (PB6/PB7), shared by the LED drivers and any other I2C peripheral
+/// through an `I2cDevice` each.
+static I2C1_BUS: StaticCell<SharedI2c> = StaticCell::new();
+
+/// Keys held at power-up to start the backlight factory test: Esc + Right.
+const FACTORY_TEST_KEYS: [(usize, usize); 2] = [(0, 0), (5, 15)];
 
 bind_interrupts!(struct Irqs {
     USB => usb::InterruptHandler<USB>;
@@ -90,7 +158,10 @@ async fn main(_spawner: Spawner) {
     let led_driver_addrs = [0x77, 0x74];
     let mut i2c_cfg_backlight = i2c::Config::default();
     i2c_cfg_backlight.frequency = Hertz(400_000);
-    let i2c = i2c::I2c::new(
+    // A stuck transfer fails after this, once the device holds the bus. The
+    // longest one is a PWM page, about 5 ms at 400 kHz.
+    i2c_cfg_backlight.timeout = Duration::from_millis(20);
+    let i2c1 = I2C1_BUS.init(Mutex::new(i2c::I2c::new(
         p.I2C1,
         p.PB6, // SCL
         p.PB7, // SDA
@@ -98,17 +169,24 @@ async fn main(_spawner: Spawner) {
         p.DMA1_CH6, // TX DMA
         p.DMA1_CH7, // RX DMA
         i2c_cfg_backlight,
-    );
-    let mut backlight = Ckled2001::<LED_DRIVER_COUNT>::new(i2c, led_driver_addrs, LED_LAYOUT);
-    if backlight.init().await.is_ok() {
-        let _ = backlight.set_color_all(255, 255, 255, 100).await;
-    }
+    )));
+    let mut backlight = Ckled2001::<_, LED_DRIVER_COUNT>::new(I2cDevice::new(i2c1), led_driver_addrs, LED_LAYOUT);
+    let _ = backlight.init().await;
 
     // Usb config
     let driver = Driver::new(p.USB, Irqs, p.PA12, p.PA11);
 
-    // Use internal flash to emulate eeprom
-    let flash = async_flash_wrapper(Flash::new_blocking(p.FLASH));
+    // Use internal flash to emulate eeprom, shared with the lighting and matrix
+    // settings
+    let flash = FLASH.init(Mutex::new(async_flash_wrapper(Flash::new_blocking(p.FLASH))));
+    let storage_flash = Partition::new(flash, RMK_STORAGE_OFFSET, RMK_STORAGE_SIZE);
+    let lighting_flash = Partition::new(flash, LIGHTING_STORAGE_OFFSET, LIGHTING_STORAGE_SIZE);
+    let matrix_flash = Partition::new(flash, MATRIX_STORAGE_OFFSET, MATRIX_STORAGE_SIZE);
+
+    // Backlight themes
+    let mut lighting = BacklightController::new(backlight, lighting_flash);
+    lighting.restore().await;
+    lighting.play(StatusAnimation::Boot).await;
 
     // Keyboard config
     let rmk_config = RmkConfig {
@@ -123,13 +201,19 @@ async fn main(_spawner: Spawner) {
         ..Default::default()
     };
 
-    // Shift register GPIO bit-bang pins
-    let data = Output::new(p.PA7, Level::Low, Speed::VeryHigh); // SER
-    let clk = Output::new(p.PA1, Level::Low, Speed::VeryHigh); // SRCLK
-    let lat = Output::new(p.PB0, Level::Low, Speed::VeryHigh); // RCLK
-
-    // Pin config for cols from shift register
-    let cols = Hc595Cols::new(data, clk, lat);
+    // Cols from the shift register: SER on PA7, SRCLK on PA1, RCLK on PB0
+    let lat = Output::new(p.PB0, Level::Low, Speed::VeryHigh);
+    let cols = if HC595_OVER_SPI {
+        let mut spi_cfg = spi::Config::default();
+        spi_cfg.frequency = Hertz(10_000_000);
+        spi_cfg.mode = spi::MODE_0;
+        spi_cfg.bit_order = spi::BitOrder::LsbFirst;
+        Hc595Cols::new_spi(Spi::new_txonly(p.SPI1, p.PA1, p.PA7, p.DMA1_CH3, spi_cfg), lat)
+    } else {
+        let data = Output::new(p.PA7, Level::Low, Speed::VeryHigh);
+        let clk = Output::new(p.PA1, Level::Low, Speed::VeryHigh);
+        Hc595Cols::new(data, clk, lat)
+    };
 
     // 6 row inputs
     let rows = [
@@ -144,39 +228,105 @@ async fn main(_spawner: Spawner) {
     // Rotary encoder
     let pin_a = ExtiInput::new(p.PA10, p.EXTI10, Pull::None, Irqs);
     let pin_b = ExtiInput::new(p.PA0, p.EXTI0, Pull::None, Irqs);
-    let mut encoder = RotaryEncoder::with_resolution(pin_a, pin_b, 4, true, 0);
+    let encoder = RotaryEncoder::with_resolution(pin_a, pin_b, 4, true, 0);
+
+    // Initialize the matrix with the saved scan and debounce settings, or
+    // MATRIX_DEFAULTS if there are none
+    let mut matrix_settings = MatrixSettings::new(matrix_flash, MATRIX_DEFAULTS);
+    matrix_settings.restore().await;
+    let scan_timing = matrix_settings.timing();
+    let (debounce, debounce_time) = matrix_settings.debounce();
+    let mut matrix = ShiftRegMatrix::<_, _, 6, 16>::builder(rows, cols)
+        .settle(scan_timing.settle)
+        .idle(scan_timing.idle)
+        .debounce(debounce, debounce_time)
+        .key_debounce(matrix_settings.key_debounce())
+        .mask_stuck(matrix_settings.mask_stuck())
+        .build();
+
+    // Boot-time key combination
+    if matrix.keys_held(&FACTORY_TEST_KEYS).await {
+        lighting.start_test_mode();
+    }
 
     // Initialize the storage and keymap
     let mut default_keymap = keymap::get_default_keymap();
     let mut default_encoder = keymap::get_default_encoder_map();
     let mut behavior_config = BehaviorConfig::default();
-    let storage_config = StorageConfig::default();
+    let storage_config = StorageConfig { num_sectors: RMK_STORAGE_SECTORS, ..Default::default() };
     let mut per_key_config = PositionalConfig::default();
     let (keymap, mut storage) = initialize_encoder_keymap_and_storage(
         &mut default_keymap,
         &mut default_encoder,
-        flash,
+        storage_flash,
         &storage_config,
         &mut behavior_config,
         &mut per_key_config,
     )
     .await;
 
-    // Initialize the matrix + keyboard
-    let mut matrix = ShiftRegMatrix::<6, 16>::new(rows, cols);
+    // Initialize the keyboard
     let mut keyboard = Keyboard::new(&keymap);
 
+    let mut encoder = Grabbable::new(encoder);
+
+    // The matrix posts its own events, so that their latency is counted up to
+    // the post.
+    let matrix_events = matrix.post_events(async |event| match input_grab::route(event) {
+        Some(event) => {
+            EVENT_CHANNEL.send(event).await;
+            true
+        }
+        None => false,
+    });
+
     // Start
-    join3(
-        run_devices!(
-            (matrix, encoder) => EVENT_CHANNEL,
+    join5(
+        join(
+            matrix_events,
+            run_devices!(
+                (encoder) => EVENT_CHANNEL,
+            ),
         ),
         keyboard.run(),
+        lighting.polling_loop(),
+        join3(host::run(), matrix_settings.run(), log_matrix()),
         run_rmk(&keymap, driver, &mut storage, rmk_config),
     )
     .await;
 }
 
+/// Log each key the first time it chatters, gets stuck or is masked, and the
+/// scan counters at info level. Their host queries are not reachable with
+/// rmk 0.8, the probe log is.
+async fn log_matrix() {
+    let mut logged = [[0u8; keymap::COL]; keymap::ROW];
+    loop {
+        Timer::after(MATRIX_LOG_INTERVAL).await;
+        let mut stats = [0u8; scan_stats::ENCODED_LEN];
+        SCAN_STATS.lock(|s| s.borrow().encode(&mut stats));
+        let short = |i: usize| u16::from_le_bytes([stats[i], stats[i + 1]]);
+        defmt::info!(
+            "scan: {} passes/s, pass avg {} max {} us, latency avg {} max {} us",
+            short(0),
+            short(2),
+            short(4),
+            short(6),
+            short(8)
+        );
+
+        KEY_HEALTH.lock(|health| {
+            for Offender { row, col, flags, chatters } in health.borrow().offenders() {
+                let logged = &mut logged[row as usize][col as usize];
+                if flags & !*logged != 0 {
+                    defmt::warn!("key ({}, {}): flags {:#x}, {} chatters", row, col, flags, chatters);
+                    *logged |= flags;
+                }
+            }
+        });
+    }
+}
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     asm::delay(10_000);
@@ -0,0 +1,29 @@
+//! Pin access used by the matrix scanner, so the same scanner serves the
+//! 74HC595 columns of this board, PCBs with plain GPIO columns, and mocks in
+//! host tests. Nothing here touches hardware.
+
+/// Drives the matrix columns. Implementations decide the active level.
+#[allow(async_fn_in_trait, reason = "only awaited by this firmware's single-threaded executor")]
+pub trait ColumnDriver {
+    /// A column write that did not go through. The columns are then in an
+    /// unknown state until the next write succeeds.
+    type Error;
+
+    /// Make `col` the only active column.
+    async fn select(&mut self, col: usize) -> Result<(), Self::Error>;
+
+    async fn unselect_all(&mut self) -> Result<(), Self::Error>;
+
+    /// Activate every column, so that any pressed key shows on its row.
+    async fn select_all(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Reads the matrix rows. Implementations decide the active level.
+#[allow(async_fn_in_trait, reason = "only awaited by this firmware's single-threaded executor")]
+pub trait RowReader {
+    /// Whether a key on `row` in an active column is pressed.
+    fn is_pressed(&self, row: usize) -> bool;
+
+    /// Return once any row reads pressed, straight away if one already does.
+    async fn wait_for_any(&mut self);
+}
@@ -0,0 +1,250 @@
+use crate::{
+    debounce::{self, Algorithm},
+    flash_record,
+    host::{self, DATA, HOST_REPLIES, MATRIX_REQUESTS, Report},
+    key_health::Offender,
+    keymap::{COL, ROW},
+    scan_stats::{self, ScanStats},
+    shiftreg_matrix::{
+        DEBOUNCE,
+        KEY_DEBOUNCE,
+        KEY_HEALTH,
+        KeyDebounceMs,
+        SCAN_STATS,
+        SCAN_TIMING,
+        SWITCH_STATE,
+        ScanTiming,
+    },
+};
+use embassy_time::Duration;
+use embedded_storage_async::nor_flash::NorFlash;
+
+/// Offset of the settings record inside the matrix flash partition.
+const RECORD_OFFSET: u32 = 0;
+/// Bump whenever the encoding changes so stale records are ignored.
+const VERSION: u8 = 4;
+/// `[settle_us: u16, idle_us: u16, algorithm, debounce_ms, mask_stuck,
+/// key_ms...]`, the per-key times row by row.
+const ENCODED_LEN: usize = 7 + ROW * COL;
+/// Offending keys that fit in one [`host::MATRIX_KEY_HEALTH`] reply.
+const OFFENDERS_PER_REPORT: usize = 7;
+
+/// Settings the matrix starts with until the host saves others, picked at
+/// build time.
+#[derive(Copy, Clone)]
+pub struct Defaults {
+    pub timing: ScanTiming,
+    pub algorithm: Algorithm,
+    /// Clamped to [`debounce::TIME_MS`].
+    pub debounce_ms: u8,
+    /// Per-key times, 0 for the default; invalid ones count as 0.
+    pub key_ms: KeyDebounceMs,
+    /// Report stuck keys as released until they are let go.
+    pub mask_stuck: bool,
+}
+
+impl Defaults {
+    pub const DEFAULT: Self = Self {
+        timing: ScanTiming::DEFAULT,
+        algorithm: Algorithm::SymmetricDefer,
+        debounce_ms: debounce::DEFAULT_TIME.as_millis() as u8,
+        key_ms: [[0; COL]; ROW],
+        mask_stuck: false,
+    };
+}
+
+impl Default for Defaults {
+    fn default() -> Self { Self::DEFAULT }
+}
+
+/// Persisted matrix scan settings, served on the matrix host channel.
+pub struct MatrixSettings<F: NorFlash> {
+    flash: F,
+    timing: ScanTiming,
+    algorithm: Algorithm,
+    debounce_ms: u8,
+    key_ms: KeyDebounceMs,
+    mask_stuck: bool,
+}
+
+impl<F: NorFlash> MatrixSettings<F> {
+    pub fn new(flash: F, defaults: Defaults) -> Self {
+        let range = debounce::TIME_MS;
+        Self {
+            flash,
+            timing: defaults.timing,
+            algorithm: defaults.algorithm,
+            debounce_ms: defaults.debounce_ms.clamp(*range.start(), *range.end()),
+            key_ms: defaults.key_ms.map(|row| row.map(|ms| if Self::valid_key_ms(ms) { ms } else { 0 })),
+            mask_stuck: defaults.mask_stuck,
+        }
+    }
+
+    /// A per-key time is 0 for the default or one of [`debounce::TIME_MS`].
+    fn valid_key_ms(ms: u8) -> bool { ms == 0 || debounce::TIME_MS.contains(&ms) }
+
+    /// Load the saved settings, keeping the defaults if there are none.
+    pub async fn restore(&mut self) {
+        let mut buf = [0u8; ENCODED_LEN];
+        if flash_record::load(&mut self.flash, RECORD_OFFSET, VERSION, &mut buf).await.is_ok() {
+            let settle_us = u16::from_le_bytes([buf[0], buf[1]]);
+            let idle_us = u16::from_le_bytes([buf[2], buf[3]]);
+            self.timing = ScanTiming::from_micros(settle_us, idle_us);
+            self.algorithm = Algorithm::from_u8(buf[4]).unwrap_or(self.algorithm);
+            let range = debounce::TIME_MS;
+            self.debounce_ms = buf[5].clamp(*range.start(), *range.end());
+            self.mask_stuck = buf[6] != 0;
+            for (key_ms, &ms) in self.key_ms.iter_mut().flatten().zip(&buf[7..]) {
+                *key_ms = if Self::valid_key_ms(ms) { ms } else { 0 };
+            }
+        }
+    }
+
+    fn apply_mask_stuck(&self) { KEY_HEALTH.lock(|health| health.borrow_mut().set_mask_stuck(self.mask_stuck)); }
+
+    #[inline]
+    pub fn timing(&self) -> ScanTiming { self.timing }
+
+    #[inline]
+    pub fn debounce(&self) -> (Algorithm, Duration) { (self.algorithm, Duration::from_millis(self.debounce_ms as u64)) }
+
+    #[inline]
+    pub fn key_debounce(&self) -> &KeyDebounceMs { &self.key_ms }
+
+    #[inline]
+    pub fn mask_stuck(&self) -> bool { self.mask_stuck }
+
+    async fn save(&mut self) -> Result<(), flash_record::RecordError> {
+        let mut buf = [0u8; ENCODED_LEN];
+        buf[..2].copy_from_slice(&self.timing.settle_us().to_le_bytes());
+        buf[2..4].copy_from_slice(&self.timing.idle_us().to_le_bytes());
+        buf[4] = self.algorithm as u8;
+        buf[5] = self.debounce_ms;
+        buf[6] = self.mask_stuck as u8;
+        for (byte, &ms) in buf[7..].iter_mut().zip(self.key_ms.iter().flatten()) {
+            *byte = ms;
+        }
+        flash_record::save(&mut self.flash, RECORD_OFFSET, VERSION, &buf).await
+    }
+
+    /// Values outside the allowed ranges are refused rather than clamped, so
+    /// the host sees that its setting did not apply.
+    fn set_value(&mut self, report: &Report) -> bool {
+        let d = &report[DATA..];
+        let us = u16::from_le_bytes([d[0], d[1]]);
+        match host::value_id(report) {
+            host::MATRIX_SETTLE_US if ScanTiming::SETTLE_US.contains(&us) => {
+                self.timing = ScanTiming::from_micros(us, self.timing.idle_us());
+                SCAN_TIMING.signal(self.timing);
+            }
+            host::MATRIX_IDLE_US if ScanTiming::IDLE_US.contains(&us) => {
+                self.timing = ScanTiming::from_micros(self.timing.settle_us(), us);
+                SCAN_TIMING.signal(self.timing);
+            }
+            host::MATRIX_DEBOUNCE_ALGORITHM => {
+                let Some(algorithm) = Algorithm::from_u8(d[0]) else {
+                    return false;
+                };
+                self.algorithm = algorithm;
+                DEBOUNCE.signal(self.debounce());
+            }
+            host::MATRIX_DEBOUNCE_MS if debounce::TIME_MS.contains(&d[0]) => {
+                self.debounce_ms = d[0];
+                DEBOUNCE.signal(self.debounce());
+            }
+            host::MATRIX_KEY_DEBOUNCE_MS if Self::valid_key_ms(d[2]) => {
+                let Some(key_ms) = self.key_ms.get_mut(d[0] as usize).and_then(|row| row.get_mut(d[1] as usize)) else {
+                    return false;
+                };
+                *key_ms = d[2];
+                KEY_DEBOUNCE.signal(self.key_ms);
+            }
+            host::MATRIX_KEY_HEALTH => KEY_HEALTH.lock(|health| health.borrow_mut().clear_chatter()),
+            host::MATRIX_MASK_STUCK => {
+                self.mask_stuck = d[0] != 0;
+                self.apply_mask_stuck();
+            }
+            host::MATRIX_SCAN_STATS => SCAN_STATS.lock(|stats| *stats.borrow_mut() = ScanStats::new()),
+            _ => return false,
+        }
+        true
+    }
+
+    fn get_value(&self, report: &mut Report) -> bool {
+        let id = host::value_id(report);
+        let d = &mut report[DATA..];
+        match id {
+            host::MATRIX_SETTLE_US => d[..2].copy_from_slice(&self.timing.settle_us().to_le_bytes()),
+            host::MATRIX_IDLE_US => d[..2].copy_from_slice(&self.timing.idle_us().to_le_bytes()),
+            host::MATRIX_DEBOUNCE_ALGORITHM => d[0] = self.algorithm as u8,
+            host::MATRIX_DEBOUNCE_MS => d[0] = self.debounce_ms,
+            host::MATRIX_KEY_DEBOUNCE_MS => {
+                let Some(&ms) = self.key_ms.get(d[0] as usize).and_then(|row| row.get(d[1] as usize)) else {
+                    return false;
+                };
+                d[2] = ms;
+            }
+            host::MATRIX_KEY_HEALTH => {
+                let first = d[0] as usize;
+                let (total, offenders) = KEY_HEALTH.lock(|health| {
+                    let health = health.borrow();
+                    let mut offenders = [None; OFFENDERS_PER_REPORT];
+                    for (slot, offender) in offenders.iter_mut().zip(health.offenders().skip(first)) {
+                        *slot = Some(offender);
+                    }
+                    (health.offenders().count(), offenders)
+                });
+                d.fill(0);
+                d[0] = total.min(u8::MAX as usize) as u8;
+                for (entry, offender) in d[1..].chunks_exact_mut(4).zip(offenders.iter().flatten()) {
+                    let Offender { row, col, flags, chatters } = *offender;
+                    entry.copy_from_slice(&[row, col, flags, chatters]);
+                }
+            }
+            host::MATRIX_MASK_STUCK => d[0] = self.mask_stuck as u8,
+            host::MATRIX_SCAN_STATS => {
+                SCAN_STATS.lock(|stats| stats.borrow().encode(&mut d[..scan_stats::ENCODED_LEN]))
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Fill in a VIA switch matrix state query. Vial only opens its matrix
+    /// tester once the keyboard is unlocked, but that check is left to the
+    /// client: rmk does not tell the firmware about unlocks. With rmk 0.8
+    /// the query never gets here, see [`host::VIA_SWITCH_MATRIX_STATE`].
+    fn switch_matrix_state(report: &mut Report) -> bool {
+        if host::channel(report) != host::VIA_SWITCH_MATRIX_STATE {
+            return false;
+        }
+        let row_len = COL.div_ceil(8);
+        let rows = SWITCH_STATE.lock(|state| state.get());
+        for (bytes, bits) in report[2..].chunks_exact_mut(row_len).zip(rows) {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = (bits >> (8 * (row_len - 1 - i))) as u8;
+            }
+        }
+        true
+    }
+
+    /// Serve matrix host requests forever.
+    pub async fn run(&mut self) {
+        loop {
+            let mut report = MATRIX_REQUESTS.receive().await;
+            let handled = match host::command(&report) {
+                host::CUSTOM_SET_VALUE => self.set_value(&report),
+                host::CUSTOM_GET_VALUE => self.get_value(&mut report),
+                host::CUSTOM_SAVE => self.save().await.is_ok(),
+                host::VIA_GET_KEYBOARD_VALUE => Self::switch_matrix_state(&mut report),
+                _ => false,
+            };
+
+            if handled {
+                HOST_REPLIES.send(report).await;
+            } else {
+                host::reply_unhandled(report).await;
+            }
+        }
+    }
+}
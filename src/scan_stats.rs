@@ -0,0 +1,114 @@
+//! Matrix scan rate and input latency counters, fed by the scanner with the
+//! times it already takes.
+//!
+//! Latency runs from reading a key's column to the event being posted to
+//! rmk's event channel, room in the channel included. Only events posted
+//! through `ShiftRegMatrix::post_events` are counted.
+
+use embassy_time::{Duration, Instant};
+
+/// Length of the window [`ScanStats::scans_per_second`] counts passes in.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// Length of [`ScanStats::encode`]'s output.
+pub const ENCODED_LEN: usize = 22;
+
+#[derive(Copy, Clone)]
+pub struct ScanStats {
+    passes: u32,
+    pass_total_us: u64,
+    pass_max_us: u32,
+    events: u32,
+    post_total_us: u64,
+    post_max_us: u32,
+    /// Sleeps on the row interrupts with every key released.
+    press_waits: u32,
+    /// Pauses between passes while keys were held.
+    idle_waits: u32,
+    /// Column writes that failed.
+    column_errors: u32,
+    window_start: Option<Instant>,
+    window_passes: u32,
+    /// Passes in the last whole window in which the matrix kept scanning.
+    scans_per_second: u32,
+}
+
+impl ScanStats {
+    pub const fn new() -> Self {
+        Self {
+            passes: 0,
+            pass_total_us: 0,
+            pass_max_us: 0,
+            events: 0,
+            post_total_us: 0,
+            post_max_us: 0,
+            press_waits: 0,
+            idle_waits: 0,
+            column_errors: 0,
+            window_start: None,
+            window_passes: 0,
+            scans_per_second: 0,
+        }
+    }
+
+    /// A full pass ran from `start` to `end`. A pass following a sleep
+    /// starts a new rate window.
+    pub fn record_pass(&mut self, start: Instant, end: Instant) {
+        let us = (end - start).as_micros();
+        self.passes = self.passes.saturating_add(1);
+        self.pass_total_us += us;
+        self.pass_max_us = self.pass_max_us.max(us as u32);
+
+        let window_start = *self.window_start.get_or_insert(start);
+        if start - window_start >= RATE_WINDOW {
+            self.scans_per_second = self.window_passes;
+            self.window_start = Some(start);
+            self.window_passes = 0;
+        }
+        self.window_passes += 1;
+    }
+
+    /// An event whose column was read at `at` was posted at `now`.
+    pub fn record_post(&mut self, at: Instant, now: Instant) {
+        let us = (now - at).as_micros();
+        self.events = self.events.saturating_add(1);
+        self.post_total_us += us;
+        self.post_max_us = self.post_max_us.max(us as u32);
+    }
+
+    /// The scanner slept, on the row interrupts if `for_press`, otherwise
+    /// for the idle time between passes.
+    pub fn record_wait(&mut self, for_press: bool) {
+        match for_press {
+            true => {
+                self.press_waits = self.press_waits.saturating_add(1);
+                self.window_start = None;
+                self.window_passes = 0;
+            }
+            false => self.idle_waits = self.idle_waits.saturating_add(1),
+        }
+    }
+
+    /// A column write failed.
+    pub fn record_column_error(&mut self) { self.column_errors = self.column_errors.saturating_add(1); }
+
+    /// `[scans_per_second, pass_avg_us, pass_max_us, post_avg_us,
+    /// post_max_us, press_waits: u32, idle_waits: u32, column_errors: u32]`,
+    /// all little-endian, the 16-bit values saturating at `0xFFFF`.
+    pub fn encode(&self, out: &mut [u8]) {
+        let avg = |total: u64, n: u32| if n == 0 { 0 } else { total / n as u64 };
+        let short = |v: u64| (v.min(u16::MAX as u64) as u16).to_le_bytes();
+
+        out[..2].copy_from_slice(&short(self.scans_per_second as u64));
+        out[2..4].copy_from_slice(&short(avg(self.pass_total_us, self.passes)));
+        out[4..6].copy_from_slice(&short(self.pass_max_us as u64));
+        out[6..8].copy_from_slice(&short(avg(self.post_total_us, self.events)));
+        out[8..10].copy_from_slice(&short(self.post_max_us as u64));
+        out[10..14].copy_from_slice(&self.press_waits.to_le_bytes());
+        out[14..18].copy_from_slice(&self.idle_waits.to_le_bytes());
+        out[18..22].copy_from_slice(&self.column_errors.to_le_bytes());
+    }
+}
+
+impl Default for ScanStats {
+    fn default() -> Self { Self::new() }
+}
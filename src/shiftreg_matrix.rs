@@ -1,21 +1,166 @@
-use crate::hc595_cols::Hc595Cols;
-use embassy_stm32::exti::ExtiInput;
-use embassy_time::{Duration, Timer};
+use crate::{
+    debounce::{self, Algorithm, Debouncer, Outcome},
+    key_health::KeyHealth,
+    keymap,
+    matrix_io::{ColumnDriver, RowReader},
+    scan_stats::ScanStats,
+};
+use core::{
+    cell::{Cell, RefCell},
+    ops::RangeInclusive,
+};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
 use rmk::{
-    debounce::{DebounceState, DebouncerTrait, default_debouncer::DefaultDebouncer},
     event::{Event, KeyboardEvent},
     input_device::InputDevice,
     matrix::KeyState,
 };
 
+/// New scan timing for a running matrix, picked up before its next pass.
+pub static SCAN_TIMING: Signal<CriticalSectionRawMutex, ScanTiming> = Signal::new();
+
+/// New debounce algorithm and time for a running matrix, picked up before
+/// its next pass.
+pub static DEBOUNCE: Signal<CriticalSectionRawMutex, (Algorithm, Duration)> = Signal::new();
+
+/// Per-key debounce times in milliseconds, 0 for the default time, sized
+/// like the keymap.
+pub type KeyDebounceMs = [[u8; keymap::COL]; keymap::ROW];
+
+/// New per-key debounce times for a running matrix, picked up before its
+/// next pass.
+pub static KEY_DEBOUNCE: Signal<CriticalSectionRawMutex, KeyDebounceMs> = Signal::new();
+
+/// Debounced switch state before the keymap, one bit per column in each
+/// row, for VIA switch matrix state queries, which rmk 0.8 does not pass on
+/// yet (see [`crate::host::VIA_SWITCH_MATRIX_STATE`]). Masked stuck keys
+/// still read as held.
+pub static SWITCH_STATE: Mutex<CriticalSectionRawMutex, Cell<[u32; keymap::ROW]>> =
+    Mutex::new(Cell::new([0; keymap::ROW]));
+
+/// Scan rate and scan-to-return counters, read by the host channel.
+pub static SCAN_STATS: Mutex<CriticalSectionRawMutex, RefCell<ScanStats>> = Mutex::new(RefCell::new(ScanStats::new()));
+
+/// Chatter and stuck keys seen by the matrix, read by the host channel.
+pub static KEY_HEALTH: Mutex<CriticalSectionRawMutex, RefCell<KeyHealth<{ keymap::ROW }, { keymap::COL }>>> =
+    Mutex::new(RefCell::new(KeyHealth::new()));
+
+/// Delays of the scan loop.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ScanTiming {
+    /// Wait after switching columns before reading the rows.
+    pub settle: Duration,
+    /// Wait between full passes while keys are held.
+    pub idle: Duration,
+}
+
+impl ScanTiming {
+    pub const DEFAULT: Self = Self { settle: Duration::from_micros(30), idle: Duration::from_micros(100) };
+    /// Allowed idle times in microseconds; 0 scans back to back.
+    pub const IDLE_US: RangeInclusive<u16> = 0..=5000;
+    /// Allowed settle times in microseconds. Below 5 us the rows may not have
+    /// recovered from the previous column; above 500 us a pass takes longer
+    /// than the debounce time.
+    pub const SETTLE_US: RangeInclusive<u16> = 5..=500;
+
+    /// Timing from microsecond values, clamped to the allowed ranges.
+    pub fn from_micros(settle_us: u16, idle_us: u16) -> Self {
+        let clamp = |us: u16, range: RangeInclusive<u16>| us.clamp(*range.start(), *range.end()) as u64;
+        Self {
+            settle: Duration::from_micros(clamp(settle_us, Self::SETTLE_US)),
+            idle: Duration::from_micros(clamp(idle_us, Self::IDLE_US)),
+        }
+    }
+
+    #[inline]
+    pub fn settle_us(&self) -> u16 { self.settle.as_micros() as u16 }
+
+    #[inline]
+    pub fn idle_us(&self) -> u16 { self.idle.as_micros() as u16 }
+}
+
+impl Default for ScanTiming {
+    fn default() -> Self { Self::DEFAULT }
+}
+
+/// Debounced changes one pass can hold before the rest wait for the next.
+const EVENT_QUEUE_LEN: usize = 16;
+
+/// Debounced key change and when its column was read.
 #[derive(Copy, Clone)]
-struct ScanPos {
-    row: usize,
-    col: usize,
+pub struct TimedEvent {
+    pub event: KeyboardEvent,
+    pub at: Instant,
+}
+
+/// Changes found by full passes, delivered in scan order.
+struct EventQueue<const N: usize> {
+    events: [Option<TimedEvent>; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> EventQueue<N> {
+    const fn new() -> Self { Self { events: [None; N], head: 0, len: 0 } }
+
+    #[inline]
+    fn is_full(&self) -> bool { self.len == N }
+
+    fn push(&mut self, event: TimedEvent) {
+        if !self.is_full() {
+            self.events[(self.head + self.len) % N] = Some(event);
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<TimedEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        event
+    }
+}
+
+/// Pause before scanning again after the columns could not be selected
+/// for the press wait.
+const COLUMN_RETRY: Duration = Duration::from_millis(1);
+
+/// Count a failed column write into [`SCAN_STATS`]. Returns whether the
+/// write went through.
+fn column_written<E>(result: Result<(), E>) -> bool {
+    if result.is_err() {
+        SCAN_STATS.lock(|stats| stats.borrow_mut().record_column_error());
+    }
+    result.is_ok()
+}
+
+fn publish_switch(row: usize, col: usize, pressed: bool) {
+    SWITCH_STATE.lock(|state| {
+        let mut rows = state.get();
+        if let Some(bits) = rows.get_mut(row) {
+            match pressed {
+                true => *bits |= 1 << col,
+                false => *bits &= !(1 << col),
+            }
+            state.set(rows);
+        }
+    });
 }
 
-impl ScanPos {
-    const fn new(row: usize, col: usize) -> Self { Self { row, col } }
+/// Keys outside the matrix are skipped, so the table may be larger.
+fn set_key_times<const ROW: usize, const COL: usize>(debouncer: &mut Debouncer<ROW, COL>, key_ms: &KeyDebounceMs) {
+    for (row, times) in key_ms.iter().enumerate() {
+        for (col, &ms) in times.iter().enumerate() {
+            debouncer.set_key_time(row, col, ms);
+        }
+    }
 }
 
 struct KeyGrid<const ROW: usize, const COL: usize> {
@@ -31,84 +176,227 @@ impl<const ROW: usize, const COL: usize> KeyGrid<ROW, COL> {
     }
 }
 
-pub struct ShiftRegMatrix<'d, const ROW: usize, const COL: usize> {
-    rows: [ExtiInput<'d>; ROW],
-    cols: Hc595Cols<'d>,
+/// Column-by-column matrix scanner. Named after the 74HC595 columns of this
+/// board, but works with any [`ColumnDriver`] and [`RowReader`].
+pub struct ShiftRegMatrix<R: RowReader, C: ColumnDriver, const ROW: usize, const COL: usize> {
+    rows: R,
+    cols: C,
 
-    debouncer: DefaultDebouncer<ROW, COL>,
+    debouncer: Debouncer<ROW, COL>,
     key_state: KeyGrid<ROW, COL>,
 
-    scan_pos: ScanPos,
+    queue: EventQueue<EVENT_QUEUE_LEN>,
+
+    timing: ScanTiming,
+}
 
-    settle: Duration,
-    idle: Duration,
+pub struct ShiftRegMatrixBuilder<R: RowReader, C: ColumnDriver, const ROW: usize, const COL: usize> {
+    rows: R,
+    cols: C,
+    timing: ScanTiming,
+    debouncer: Debouncer<ROW, COL>,
+    mask_stuck: bool,
 }
 
-impl<'d, const ROW: usize, const COL: usize> ShiftRegMatrix<'d, ROW, COL> {
-    pub fn new(rows: [ExtiInput<'d>; ROW], mut cols: Hc595Cols<'d>) -> Self {
-        cols.unselect_all();
+impl<R: RowReader, C: ColumnDriver, const ROW: usize, const COL: usize> ShiftRegMatrixBuilder<R, C, ROW, COL> {
+    /// Column settle time, clamped to [`ScanTiming::SETTLE_US`].
+    pub fn settle(mut self, settle: Duration) -> Self {
+        self.timing = ScanTiming::from_micros(settle.as_micros() as u16, self.timing.idle_us());
+        self
+    }
 
-        Self {
+    /// Time between passes while keys are held, clamped to
+    /// [`ScanTiming::IDLE_US`].
+    pub fn idle(mut self, idle: Duration) -> Self {
+        self.timing = ScanTiming::from_micros(self.timing.settle_us(), idle.as_micros() as u16);
+        self
+    }
+
+    /// Debounce algorithm and time, clamped to [`debounce::TIME_MS`].
+    pub fn debounce(mut self, algorithm: Algorithm, time: Duration) -> Self {
+        let range = debounce::TIME_MS;
+        let ms = time.as_millis().clamp(*range.start() as u64, *range.end() as u64);
+        self.debouncer.set(algorithm, Duration::from_millis(ms));
+        self
+    }
+
+    /// Per-key debounce times overriding the default one, see
+    /// [`Debouncer::set_key_time`].
+    pub fn key_debounce(mut self, key_ms: &KeyDebounceMs) -> Self {
+        set_key_times(&mut self.debouncer, key_ms);
+        self
+    }
+
+    /// Report a key held for too long as released until it is let go, see
+    /// [`KeyHealth::set_mask_stuck`]. Applies to [`KEY_HEALTH`].
+    pub fn mask_stuck(mut self, on: bool) -> Self {
+        self.mask_stuck = on;
+        self
+    }
+
+    pub fn build(self) -> ShiftRegMatrix<R, C, ROW, COL> {
+        KEY_HEALTH.lock(|health| health.borrow_mut().set_mask_stuck(self.mask_stuck));
+        ShiftRegMatrix {
+            rows: self.rows,
+            cols: self.cols,
+            debouncer: self.debouncer,
+            key_state: KeyGrid::new(),
+            queue: EventQueue::new(),
+            timing: self.timing,
+        }
+    }
+}
+
+impl<R: RowReader, C: ColumnDriver, const ROW: usize, const COL: usize> ShiftRegMatrix<R, C, ROW, COL> {
+    /// Scanner starting with [`ScanTiming::DEFAULT`], symmetric debouncing
+    /// and stuck keys left alone. Every column write sets all columns, so their
+    /// power-up state does not matter.
+    pub fn builder(rows: R, cols: C) -> ShiftRegMatrixBuilder<R, C, ROW, COL> {
+        ShiftRegMatrixBuilder {
             rows,
             cols,
-            debouncer: DefaultDebouncer::new(),
-            key_state: KeyGrid::new(),
-            scan_pos: ScanPos::new(0, 0),
-            settle: Duration::from_micros(30),
-            idle: Duration::from_micros(100),
+            timing: ScanTiming::DEFAULT,
+            debouncer: Debouncer::with_algorithm(Algorithm::SymmetricDefer, debounce::DEFAULT_TIME),
+            mask_stuck: false,
         }
     }
 
-    async fn scan_until_event(&mut self) -> Option<KeyboardEvent> {
-        let rows: &[ExtiInput<'d>; ROW] = &self.rows;
-        let settle: Duration = self.settle;
+    /// Whether all `(row, col)` keys are held right now, read directly
+    /// without debouncing. Meant for key combinations checked at power-up;
+    /// a key whose column cannot be selected reads as not held.
+    pub async fn keys_held(&mut self, keys: &[(usize, usize)]) -> bool {
+        for &(row, col) in keys {
+            if !column_written(self.cols.select(col).await) {
+                return false;
+            }
+            Timer::after(self.timing.settle).await;
+            let held = self.rows.is_pressed(row);
+            column_written(self.cols.unselect_all().await);
 
-        let cols: &mut Hc595Cols<'d> = &mut self.cols;
-        let debouncer: &mut DefaultDebouncer<ROW, COL> = &mut self.debouncer;
-        let key_state: &mut KeyGrid<ROW, COL> = &mut self.key_state;
-        let scan_pos: &mut ScanPos = &mut self.scan_pos;
+            if !held {
+                return false;
+            }
+        }
+        true
+    }
 
-        let start = *scan_pos;
+    /// No key is held, as far as the debounced state goes.
+    fn all_released(&self) -> bool { self.key_state.cells.iter().flatten().all(|ks| !ks.pressed) }
 
-        for c in (start.col..COL).chain(0..start.col) {
-            cols.select_col_active_low(c);
-            Timer::after(settle).await;
+    /// Select every column and sleep until a key shows on a row. A press
+    /// still waiting on the debouncer returns straight away. If the columns
+    /// cannot be selected no press would show, so this returns after
+    /// [`COLUMN_RETRY`] to scan again.
+    async fn wait_for_press(&mut self) {
+        if !column_written(self.cols.select_all().await) {
+            Timer::after(COLUMN_RETRY).await;
+            return;
+        }
+        Timer::after(self.timing.settle).await;
+        self.rows.wait_for_any().await;
+        column_written(self.cols.unselect_all().await);
+    }
 
-            let r_start = if c == start.col { start.row } else { 0 };
+    /// Read the whole matrix once, queueing every debounced change with the
+    /// time its column was read. When the queue fills up, the remaining keys
+    /// are left to the next pass. A column that cannot be selected is not
+    /// read this pass, so its keys keep their state.
+    async fn scan_pass(&mut self) {
+        if let Some(timing) = SCAN_TIMING.try_take() {
+            self.timing = timing;
+        }
+        if let Some((algorithm, time)) = DEBOUNCE.try_take() {
+            self.debouncer.set(algorithm, time);
+        }
+        if let Some(key_ms) = KEY_DEBOUNCE.try_take() {
+            set_key_times(&mut self.debouncer, &key_ms);
+        }
 
-            for (r, row_pin) in rows.iter().enumerate().skip(r_start) {
-                let pressed = row_pin.is_low();
+        let start = Instant::now();
+        for c in 0..COL {
+            if !column_written(self.cols.select(c).await) {
+                continue;
+            }
+            Timer::after(self.timing.settle).await;
+            let at = Instant::now();
+
+            for r in 0..ROW {
+                if self.queue.is_full() {
+                    break;
+                }
+                let pressed = self.rows.is_pressed(r);
 
-                let Some(ks) = key_state.get_mut(r, c) else {
+                let Some(ks) = self.key_state.get_mut(r, c) else {
                     continue;
                 };
 
-                let st = debouncer.detect_change_with_debounce(r, c, pressed, ks);
-                if let DebounceState::Debounced = st {
+                if self.debouncer.update(r, c, pressed, ks.pressed, at) == Outcome::Changed {
                     ks.pressed = pressed;
+                    publish_switch(r, c, pressed);
+                    if KEY_HEALTH.lock(|health| health.borrow_mut().changed(r, c, pressed, at)) {
+                        self.queue.push(TimedEvent { event: KeyboardEvent::key(r as u8, c as u8, pressed), at });
+                    }
+                }
+            }
 
-                    cols.unselect_all();
-                    *scan_pos = ScanPos::new(r, c);
+            column_written(self.cols.unselect_all().await);
+        }
+
+        let now = Instant::now();
+        SCAN_STATS.lock(|stats| stats.borrow_mut().record_pass(start, now));
 
-                    return Some(KeyboardEvent::key(r as u8, c as u8, pressed));
+        // Masked keys get a release now and stay held in `key_state`, so
+        // their real release is still debounced before they count again.
+        let queue = &mut self.queue;
+        KEY_HEALTH.lock(|health| {
+            health.borrow_mut().check_stuck(now, |r, c| {
+                if queue.is_full() {
+                    return false;
                 }
+                queue.push(TimedEvent { event: KeyboardEvent::key(r as u8, c as u8, false), at: now });
+                true
+            })
+        });
+    }
+
+    /// Next debounced change with its timestamp. Scans while any key is held
+    /// or settling; once a pass finds every key released and debounced,
+    /// waits for the row interrupts instead.
+    pub async fn read_timed_event(&mut self) -> TimedEvent {
+        loop {
+            if let Some(event) = self.queue.pop() {
+                return event;
             }
 
-            cols.unselect_all();
+            self.scan_pass().await;
+            if self.queue.len > 0 {
+                continue;
+            }
+            let for_press = self.all_released();
+            SCAN_STATS.lock(|stats| stats.borrow_mut().record_wait(for_press));
+            if for_press {
+                self.wait_for_press().await;
+            } else {
+                Timer::after(self.timing.idle).await;
+            }
         }
-
-        *scan_pos = ScanPos::new(0, 0);
-        None
     }
-}
 
-impl<'d, const ROW: usize, const COL: usize> InputDevice for ShiftRegMatrix<'d, ROW, COL> {
-    async fn read_event(&mut self) -> Event {
+    /// Hand every event to `post`, which returns once it is posted, or
+    /// `false` if it went elsewhere. Posted events count into [`SCAN_STATS`]
+    /// as latency, from reading the key's column to `post` returning.
+    pub async fn post_events(&mut self, mut post: impl AsyncFnMut(Event) -> bool) -> ! {
         loop {
-            if let Some(ev) = self.scan_until_event().await {
-                return Event::Key(ev);
+            let TimedEvent { event, at } = self.read_timed_event().await;
+            if post(Event::Key(event)).await {
+                SCAN_STATS.lock(|stats| stats.borrow_mut().record_post(at, Instant::now()));
             }
-            Timer::after(self.idle).await;
         }
     }
 }
+
+impl<R: RowReader, C: ColumnDriver, const ROW: usize, const COL: usize> InputDevice for ShiftRegMatrix<R, C, ROW, COL> {
+    /// rmk events carry no time, so nothing is counted as latency here; see
+    /// [`ShiftRegMatrix::post_events`].
+    async fn read_event(&mut self) -> Event { Event::Key(self.read_timed_event().await.event) }
+}
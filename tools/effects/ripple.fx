@@ -0,0 +1,4 @@
+# Rainbow drifting across the board; pressed keys flash white and fade out.
+h = hue + x - t * speed / 2048
+s = min(sat, hit / 2)
+v = val
@@ -0,0 +1,72 @@
+//! Stand-in for the few rmk items the matrix scanner uses, with the same
+//! paths and shapes as rmk 0.8. Events compare equal so tests can assert on
+//! them.
+
+pub mod event {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct KeyPos {
+        pub row: u8,
+        pub col: u8,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum KeyboardEventPos {
+        Key(KeyPos),
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct KeyboardEvent {
+        pub pressed: bool,
+        pub pos: KeyboardEventPos,
+    }
+
+    impl KeyboardEvent {
+        pub fn key(row: u8, col: u8, pressed: bool) -> Self {
+            Self { pressed, pos: KeyboardEventPos::Key(KeyPos { row, col }) }
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum Event {
+        Key(KeyboardEvent),
+    }
+}
+
+pub mod input_device {
+    use super::event::Event;
+
+    #[allow(async_fn_in_trait, reason = "mirrors rmk's trait")]
+    pub trait InputDevice {
+        async fn read_event(&mut self) -> Event;
+    }
+}
+
+pub mod matrix {
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct KeyState {
+        pub pressed: bool,
+    }
+}
+
+pub mod debounce {
+    use super::matrix::KeyState;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub enum DebounceState {
+        Debounced,
+        InProgress,
+        Ignored,
+    }
+
+    pub trait DebouncerTrait {
+        fn new() -> Self;
+
+        fn detect_change_with_debounce(
+            &mut self,
+            in_idx: usize,
+            out_idx: usize,
+            pin_state: bool,
+            key_state: &KeyState,
+        ) -> DebounceState;
+    }
+}
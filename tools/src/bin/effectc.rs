@@ -0,0 +1,46 @@
+//! Compile a lighting effect source file to bytecode.
+//!
+//! Usage: `effectc <source> [-o <output>]`. Without `-o` the bytecode is
+//! printed as hex. Upload the output with `scripts/upload_effect.py`.
+
+use q1pro_tools::effect_compiler::compile;
+use std::{env, fs, process::ExitCode};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (source, output) = match args.as_slice() {
+        [source] => (source, None),
+        [source, flag, output] if flag == "-o" => (source, Some(output)),
+        _ => {
+            eprintln!("usage: effectc <source> [-o <output>]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let text = match fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{source}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let code = match compile(&text) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{source}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, &code) {
+                eprintln!("{path}: {e}");
+                return ExitCode::FAILURE;
+            }
+            eprintln!("{path}: {} bytes", code.len());
+        }
+        None => println!("{}", code.iter().map(|b| format!("{b:02x}")).collect::<String>()),
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,132 @@
+//! Preview lighting effects without a board.
+//!
+//! Usage:
+//!
+//! ```text
+//! lightsim [--effect N | --program FILE.fx] [--color H S V] [--speed N]
+//!          [--time MS] [--frames N] [--interval MS] [--scale PX]
+//!          [--out FILE.png | FILE.ppm]
+//! ```
+//!
+//! Without `--out`, frames are played back in the terminal with truecolor
+//! escapes. With several frames, the output file names get a frame number.
+
+use q1pro_tools::{
+    effect_compiler::compile,
+    led_mappings::iso_knob::LED_COUNT,
+    lighting::{bytecode::Program, color::Hsv, effect::Effect, theme::Theme},
+    simulator::{Frame, Layout, program_colors, theme_colors},
+};
+use std::{env, fs, path::Path, process::ExitCode, thread, time::Duration};
+
+struct Options {
+    theme: Theme,
+    program: Option<Program>,
+    time: u32,
+    frames: u32,
+    interval: u32,
+    scale: Option<usize>,
+    out: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        theme: Theme::new(Effect::CycleAll, Hsv::new(0, 255, 255), 128),
+        program: None,
+        time: 0,
+        frames: 1,
+        interval: 33,
+        scale: None,
+        out: None,
+    };
+
+    let mut args = env::args().skip(1);
+    let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+    fn num<T: std::str::FromStr>(name: &str, text: String) -> Result<T, String> {
+        text.parse().map_err(|_| format!("bad value `{text}` for {name}"))
+    }
+
+    while let Ok(arg) = value("") {
+        match arg.as_str() {
+            "--effect" => {
+                let n: u8 = num("--effect", value("--effect")?)?;
+                opts.theme.effect = Effect::from_u8(n).ok_or(format!("unknown effect {n}"))?;
+            }
+            "--program" => {
+                let path = value("--program")?;
+                let source = fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+                let code = compile(&source).map_err(|e| format!("{path}: {e}"))?;
+                opts.program = Some(Program::new(&code).map_err(|e| format!("{path}: {e:?}"))?);
+                opts.theme.effect = Effect::Custom;
+            }
+            "--color" => {
+                let h = num("--color", value("--color")?)?;
+                let s = num("--color", value("--color")?)?;
+                let v = num("--color", value("--color")?)?;
+                opts.theme.color = Hsv::new(h, s, v);
+            }
+            "--speed" => opts.theme.speed = num("--speed", value("--speed")?)?,
+            "--time" => opts.time = num("--time", value("--time")?)?,
+            "--frames" => opts.frames = num("--frames", value("--frames")?)?,
+            "--interval" => opts.interval = num("--interval", value("--interval")?)?,
+            "--scale" => opts.scale = Some(num("--scale", value("--scale")?)?),
+            "--out" => opts.out = Some(value("--out")?),
+            _ => return Err(format!("unknown argument `{arg}`")),
+        }
+    }
+    Ok(opts)
+}
+
+/// `frame.png` becomes `frame-0003.png` when writing several frames.
+fn numbered(path: &str, index: u32, frames: u32) -> String {
+    if frames == 1 {
+        return path.to_string();
+    }
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("frame");
+    let ext = p.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    p.with_file_name(format!("{stem}-{index:04}.{ext}")).to_string_lossy().into_owned()
+}
+
+fn main() -> ExitCode {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("lightsim: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let layout = Layout::board();
+    let scale = opts.scale.unwrap_or(if opts.out.is_some() { 24 } else { 6 });
+    let hits = [None; LED_COUNT];
+
+    for i in 0..opts.frames {
+        let now_ms = opts.time + i * opts.interval;
+        let colors = match &opts.program {
+            Some(program) => program_colors(program, &opts.theme, now_ms, &hits),
+            None => theme_colors(&opts.theme, now_ms),
+        };
+        let frame = Frame::render(&layout, scale, &colors);
+
+        match &opts.out {
+            Some(out) => {
+                let path = numbered(out, i, opts.frames);
+                let bytes = if path.ends_with(".ppm") { frame.to_ppm() } else { frame.to_png() };
+                if let Err(e) = fs::write(&path, bytes) {
+                    eprintln!("{path}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            None => {
+                if i > 0 {
+                    // Back to the top of the previous frame.
+                    print!("\x1b[{}A", frame.height.div_ceil(2));
+                    thread::sleep(Duration::from_millis(opts.interval as u64));
+                }
+                print!("{}", frame.to_ansi());
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
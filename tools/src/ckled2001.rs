@@ -0,0 +1,6 @@
+#[path = "../../src/ckled2001/driver.rs"]
+pub mod driver;
+#[path = "../../src/ckled2001/led_address.rs"]
+pub mod led_address;
+#[path = "../../src/ckled2001/registers.rs"]
+pub mod registers;
@@ -0,0 +1,286 @@
+//! Compiler from a small expression language to lighting bytecode.
+//!
+//! A source file assigns the output channels, one per line:
+//!
+//! ```text
+//! # hue follows the key position, keys light up when hit
+//! h = hue + x - t / 8
+//! v = max(40, 255 - hit / 2)
+//! ```
+//!
+//! `h`, `s` and `v` default to the theme's base color. Expressions use
+//! integer arithmetic with C precedence for `* / % + - << >> & ^ | < > ==`,
+//! unary `-` and `!`, parentheses, the inputs `t x y hit hue sat val speed
+//! led` and the functions `min(a, b)`, `max(a, b)`, `abs(a)`, `tri(a)`,
+//! `sqrt(a)` and `if(cond, then, else)`.
+
+use crate::lighting::bytecode::{MAX_PROGRAM_LEN, Program, op};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "line {}: {}", self.line, self.message) }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 18] =
+    ["<<", ">>", "==", "+", "-", "*", "/", "%", "&", "|", "^", "<", ">", "!", "(", ")", ",", "="];
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let text = &rest[..end];
+            let value = match text.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => text.parse(),
+            };
+            tokens.push(Token::Number(value.map_err(|_| format!("bad number `{text}`"))?));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected `{c}`"));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Binary operators by precedence level, lowest first.
+const LEVELS: [&[(&str, u8)]; 7] = [
+    &[("<", op::LT), (">", op::GT), ("==", op::EQ)],
+    &[("|", op::OR)],
+    &[("^", op::XOR)],
+    &[("&", op::AND)],
+    &[("<<", op::SHL), (">>", op::SHR)],
+    &[("+", op::ADD), ("-", op::SUB)],
+    &[("*", op::MUL), ("/", op::DIV), ("%", op::MOD)],
+];
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    code: &'a mut Vec<u8>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> { self.tokens.get(self.pos) }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) { Ok(()) } else { Err(format!("expected `{op}`")) }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<(), String> {
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        self.expr(level + 1)?;
+        loop {
+            let Some(&(_, opcode)) = ops.iter().find(|(sym, _)| matches!(self.peek(), Some(Token::Op(o)) if o == sym))
+            else {
+                return Ok(());
+            };
+            self.pos += 1;
+            self.expr(level + 1)?;
+            self.code.push(opcode);
+            // Comparisons do not chain.
+            if level == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<(), String> {
+        if self.eat("-") {
+            self.unary()?;
+            self.code.push(op::NEG);
+        } else if self.eat("!") {
+            self.unary()?;
+            self.code.push(op::NOT);
+        } else {
+            self.atom()?;
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), String> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                self.push_const(n)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if self.eat("(") { self.call(&name) } else { self.input(&name) }
+            }
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                self.expr(0)?;
+                self.expect(")")
+            }
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of line".into()),
+        }
+    }
+
+    fn push_const(&mut self, n: i64) -> Result<(), String> {
+        if let Ok(b) = i8::try_from(n) {
+            self.code.extend([op::PUSH8, b as u8]);
+        } else if let Ok(w) = i16::try_from(n) {
+            self.code.push(op::PUSH16);
+            self.code.extend(w.to_le_bytes());
+        } else {
+            return Err(format!("constant {n} out of the 16-bit range"));
+        }
+        Ok(())
+    }
+
+    fn input(&mut self, name: &str) -> Result<(), String> {
+        let opcode = match name {
+            "t" => op::TIME,
+            "x" => op::X,
+            "y" => op::Y,
+            "hit" => op::HIT,
+            "hue" => op::HUE,
+            "sat" => op::SAT,
+            "val" => op::VAL,
+            "speed" => op::SPEED,
+            "led" => op::LED,
+            _ => return Err(format!("unknown input `{name}`")),
+        };
+        self.code.push(opcode);
+        Ok(())
+    }
+
+    fn args(&mut self, count: usize) -> Result<(), String> {
+        for i in 0..count {
+            if i > 0 {
+                self.expect(",")?;
+            }
+            self.expr(0)?;
+        }
+        self.expect(")")
+    }
+
+    fn call(&mut self, name: &str) -> Result<(), String> {
+        let (count, opcode) = match name {
+            "min" => (2, op::MIN),
+            "max" => (2, op::MAX),
+            "abs" => (1, op::ABS),
+            "tri" => (1, op::TRI),
+            "sqrt" => (1, op::SQRT),
+            "if" => return self.conditional(),
+            _ => return Err(format!("unknown function `{name}`")),
+        };
+        self.args(count)?;
+        self.code.push(opcode);
+        Ok(())
+    }
+
+    /// `if(c, a, b)` as `c JZ else; a JMP end; else: b; end:`.
+    fn conditional(&mut self) -> Result<(), String> {
+        self.expr(0)?;
+        self.expect(",")?;
+        let jz = self.code.len();
+        self.code.extend([op::JZ, 0]);
+        self.expr(0)?;
+        self.expect(",")?;
+        let jmp = self.code.len();
+        self.code.extend([op::JMP, 0]);
+        self.patch(jz)?;
+        self.expr(0)?;
+        self.expect(")")?;
+        self.patch(jmp)
+    }
+
+    /// Point the jump at `at` to the current end of the code.
+    fn patch(&mut self, at: usize) -> Result<(), String> {
+        let skip = self.code.len() - (at + 2);
+        self.code[at + 1] = u8::try_from(skip).map_err(|_| "branch of `if` too long".to_string())?;
+        Ok(())
+    }
+}
+
+/// Compile `source` to bytecode accepted by the firmware.
+pub fn compile(source: &str) -> Result<Vec<u8>, CompileError> {
+    let mut channels: [Option<Vec<u8>>; 3] = Default::default();
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let err = |message: String| CompileError { line, message };
+
+        let text = raw.split('#').next().unwrap_or_default();
+        let tokens = tokenize(text).map_err(err)?;
+        let Some((target, tokens)) = tokens.split_first() else {
+            continue;
+        };
+
+        let channel = match target {
+            Token::Ident(name) if name == "h" => 0,
+            Token::Ident(name) if name == "s" => 1,
+            Token::Ident(name) if name == "v" => 2,
+            _ => return Err(err("expected `h =`, `s =` or `v =`".into())),
+        };
+        if tokens.first() != Some(&Token::Op("=")) {
+            return Err(err("expected `=`".into()));
+        }
+        if channels[channel].is_some() {
+            return Err(err("channel assigned twice".into()));
+        }
+
+        let mut code = Vec::new();
+        let mut parser = Parser { tokens: &tokens[1..], pos: 0, code: &mut code };
+        parser.expr(0).map_err(err)?;
+        if let Some(token) = parser.peek() {
+            return Err(err(format!("unexpected {token:?}")));
+        }
+        channels[channel] = Some(code);
+    }
+
+    let mut code = Vec::new();
+    for (channel, default) in channels.into_iter().zip([op::HUE, op::SAT, op::VAL]) {
+        code.extend(channel.unwrap_or_else(|| vec![default]));
+    }
+    code.push(op::OUT);
+
+    if code.len() > MAX_PROGRAM_LEN {
+        return Err(CompileError {
+            line: 0,
+            message: format!("program is {} bytes, the limit is {MAX_PROGRAM_LEN}", code.len()),
+        });
+    }
+    Program::new(&code).map_err(|e| CompileError { line: 0, message: format!("rejected by the verifier: {e:?}") })?;
+    Ok(code)
+}
@@ -0,0 +1,2 @@
+#[path = "../../src/led_mappings/iso_knob.rs"]
+pub mod iso_knob;
@@ -0,0 +1,37 @@
+//! Host builds of the firmware modules that do not touch hardware, plus the
+//! tools built on top of them. Modules under `ckled2001`, `led_mappings` and
+//! `lighting`, and `debounce`, `flash_record`, `host`, `key_health`,
+//! `matrix_io`, `matrix_settings`, `scan_stats` and `shiftreg_matrix`, are
+//! the firmware's own source files, so they mirror its module tree.
+
+pub mod ckled2001;
+#[path = "../../src/debounce.rs"]
+pub mod debounce;
+pub mod effect_compiler;
+#[path = "../../src/flash_record.rs"]
+pub mod flash_record;
+#[path = "../../src/host.rs"]
+pub mod host;
+#[path = "../../src/key_health.rs"]
+pub mod key_health;
+pub mod led_mappings;
+pub mod lighting;
+#[path = "../../src/matrix_io.rs"]
+pub mod matrix_io;
+pub mod matrix_mock;
+#[path = "../../src/matrix_settings.rs"]
+pub mod matrix_settings;
+pub mod matrix_sim;
+#[path = "../../src/scan_stats.rs"]
+pub mod scan_stats;
+#[path = "../../src/shiftreg_matrix.rs"]
+pub mod shiftreg_matrix;
+pub mod simulator;
+
+/// Stand-in for the firmware keymap, which needs rmk. Only sizes the layer
+/// table of `LightingConfig` and the matrix tables of `shiftreg_matrix`.
+pub mod keymap {
+    pub const NUM_LAYER: usize = 1;
+    pub const COL: usize = 16;
+    pub const ROW: usize = 6;
+}
@@ -0,0 +1,20 @@
+#[path = "../../src/lighting/bytecode.rs"]
+pub mod bytecode;
+#[path = "../../src/lighting/color.rs"]
+pub mod color;
+#[path = "../../src/lighting/effect.rs"]
+pub mod effect;
+#[path = "../../src/lighting/knob.rs"]
+pub mod knob;
+#[path = "../../src/lighting/overlay.rs"]
+pub mod overlay;
+#[path = "../../src/lighting/paint.rs"]
+pub mod paint;
+#[path = "../../src/lighting/setup.rs"]
+pub mod setup;
+#[path = "../../src/lighting/test_mode.rs"]
+pub mod test_mode;
+#[path = "../../src/lighting/theme.rs"]
+pub mod theme;
+#[path = "../../src/lighting/transition.rs"]
+pub mod transition;
@@ -0,0 +1,118 @@
+//! Matrix pins for host tests: the test presses and releases keys, the
+//! scanner sees them through [`ColumnDriver`] and [`RowReader`] like on the
+//! board.
+
+use crate::matrix_io::{ColumnDriver, RowReader};
+use std::{
+    cell::RefCell,
+    future::poll_fn,
+    rc::Rc,
+    task::{Poll, Waker},
+};
+
+struct Board<const ROW: usize, const COL: usize> {
+    pressed: [[bool; COL]; ROW],
+    active: [bool; COL],
+    /// Scanner waiting in [`RowReader::wait_for_any`].
+    waiter: Option<Waker>,
+    selects: usize,
+    /// Column whose writes fail, as a broken bus would.
+    faulty: Option<usize>,
+}
+
+impl<const ROW: usize, const COL: usize> Board<ROW, COL> {
+    fn row_pressed(&self, row: usize) -> bool {
+        self.pressed.get(row).is_some_and(|keys| keys.iter().zip(self.active).any(|(&key, active)| key && active))
+    }
+}
+
+/// Test side of a mock matrix. Hand [`cols`](Self::cols) and
+/// [`rows`](Self::rows) to the scanner and drive the keys from here.
+#[derive(Clone)]
+pub struct MockMatrix<const ROW: usize, const COL: usize>(Rc<RefCell<Board<ROW, COL>>>);
+
+impl<const ROW: usize, const COL: usize> MockMatrix<ROW, COL> {
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(Board {
+            pressed: [[false; COL]; ROW],
+            active: [false; COL],
+            waiter: None,
+            selects: 0,
+            faulty: None,
+        })))
+    }
+
+    pub fn cols(&self) -> MockCols<ROW, COL> { MockCols(self.0.clone()) }
+
+    pub fn rows(&self) -> MockRows<ROW, COL> { MockRows(self.0.clone()) }
+
+    /// Set the contact state of one key, waking a scanner waiting for input.
+    pub fn set(&self, row: usize, col: usize, pressed: bool) {
+        let mut board = self.0.borrow_mut();
+        board.pressed[row][col] = pressed;
+        if let Some(waker) = board.waiter.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn press(&self, row: usize, col: usize) { self.set(row, col, true) }
+
+    pub fn release(&self, row: usize, col: usize) { self.set(row, col, false) }
+
+    /// Column writes so far, of any kind.
+    pub fn selects(&self) -> usize { self.0.borrow().selects }
+
+    /// Make selecting `col` fail, or no column with `None`. Writes that
+    /// fail change nothing.
+    pub fn set_faulty(&self, col: Option<usize>) { self.0.borrow_mut().faulty = col; }
+}
+
+/// Error of a failed [`MockCols`] write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ColumnFault;
+
+impl<const ROW: usize, const COL: usize> Default for MockMatrix<ROW, COL> {
+    fn default() -> Self { Self::new() }
+}
+
+pub struct MockCols<const ROW: usize, const COL: usize>(Rc<RefCell<Board<ROW, COL>>>);
+
+impl<const ROW: usize, const COL: usize> MockCols<ROW, COL> {
+    fn activate(&mut self, f: impl Fn(usize) -> bool) -> Result<(), ColumnFault> {
+        let mut board = self.0.borrow_mut();
+        board.selects += 1;
+        if board.faulty.is_some_and(&f) {
+            return Err(ColumnFault);
+        }
+        board.active = std::array::from_fn(f);
+        Ok(())
+    }
+}
+
+impl<const ROW: usize, const COL: usize> ColumnDriver for MockCols<ROW, COL> {
+    type Error = ColumnFault;
+
+    async fn select(&mut self, col: usize) -> Result<(), ColumnFault> { self.activate(|c| c == col) }
+
+    async fn unselect_all(&mut self) -> Result<(), ColumnFault> { self.activate(|_| false) }
+
+    async fn select_all(&mut self) -> Result<(), ColumnFault> { self.activate(|_| true) }
+}
+
+pub struct MockRows<const ROW: usize, const COL: usize>(Rc<RefCell<Board<ROW, COL>>>);
+
+impl<const ROW: usize, const COL: usize> RowReader for MockRows<ROW, COL> {
+    fn is_pressed(&self, row: usize) -> bool { self.0.borrow().row_pressed(row) }
+
+    async fn wait_for_any(&mut self) {
+        poll_fn(|cx| {
+            let mut board = self.0.borrow_mut();
+            if (0..ROW).any(|row| board.row_pressed(row)) {
+                return Poll::Ready(());
+            }
+            board.waiter = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
@@ -0,0 +1,172 @@
+//! Runs the matrix scanner against a [`MockMatrix`] on a virtual clock, so
+//! tests can script presses, bounce and chords to the microsecond and check
+//! the exact events that come out.
+//!
+//! This module is the embassy-time driver of every binary linking the
+//! tools crate. Time only moves inside [`run`], jumping straight to the next
+//! timer or scripted change, or through [`advance_to_ms`].
+
+use crate::{
+    key_health::KeyHealth,
+    matrix_mock::MockMatrix,
+    scan_stats::ScanStats,
+    shiftreg_matrix::{DEBOUNCE, KEY_DEBOUNCE, KEY_HEALTH, SCAN_STATS, SCAN_TIMING, SWITCH_STATE},
+};
+use embassy_time_driver::Driver;
+use rmk::{
+    event::{Event, KeyboardEvent},
+    input_device::InputDevice,
+};
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::pin,
+    sync::{
+        Mutex,
+        MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+struct SimClock {
+    now: AtomicU64,
+    /// Timer deadlines not reached yet. Their wakers are not needed: the
+    /// future is polled after every step anyway.
+    deadlines: Mutex<Vec<u64>>,
+}
+
+impl SimClock {
+    fn next_deadline(&self) -> Option<u64> { self.deadlines.lock().unwrap().iter().copied().min() }
+
+    fn advance_to(&self, at: u64) {
+        self.now.fetch_max(at, Ordering::SeqCst);
+        let now = self.now.load(Ordering::SeqCst);
+        self.deadlines.lock().unwrap().retain(|&deadline| deadline > now);
+    }
+}
+
+impl Driver for SimClock {
+    fn now(&self) -> u64 { self.now.load(Ordering::SeqCst) }
+
+    fn schedule_wake(&self, at: u64, _waker: &Waker) { self.deadlines.lock().unwrap().push(at); }
+}
+
+embassy_time_driver::time_driver_impl!(static CLOCK: SimClock = SimClock { now: AtomicU64::new(0), deadlines: Mutex::new(Vec::new()) });
+
+/// One scripted contact change, at a time in microseconds from the start.
+#[derive(Copy, Clone, Debug)]
+pub struct Contact {
+    pub at_us: u64,
+    pub row: usize,
+    pub col: usize,
+    pub closed: bool,
+}
+
+impl Contact {
+    pub const fn press(at_ms: u64, row: usize, col: usize) -> Self {
+        Self { at_us: at_ms * 1000, row, col, closed: true }
+    }
+
+    pub const fn release(at_ms: u64, row: usize, col: usize) -> Self {
+        Self { at_us: at_ms * 1000, row, col, closed: false }
+    }
+
+    /// Contact going `closed` at `at_ms`, then bouncing back and forth
+    /// `times` more times, 200 us per change, before settling `closed`.
+    pub fn bounce(at_ms: u64, row: usize, col: usize, times: usize, closed: bool) -> Vec<Self> {
+        (0..=2 * times)
+            .map(|i| Self { at_us: at_ms * 1000 + i as u64 * 200, row, col, closed: closed == (i % 2 == 0) })
+            .collect()
+    }
+}
+
+/// Only one simulation runs at a time: the clock and the scanner's statics
+/// are shared.
+static RUNNING: Mutex<()> = Mutex::new(());
+
+/// Take the simulator, with the clock back at zero and the scanner's
+/// statics as at boot. Hold the guard for the whole test.
+pub fn start() -> MutexGuard<'static, ()> {
+    let guard = RUNNING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    CLOCK.now.store(0, Ordering::SeqCst);
+    CLOCK.deadlines.lock().unwrap().clear();
+
+    SCAN_TIMING.reset();
+    DEBOUNCE.reset();
+    KEY_DEBOUNCE.reset();
+    KEY_HEALTH.lock(|health| *health.borrow_mut() = KeyHealth::new());
+    SCAN_STATS.lock(|stats| *stats.borrow_mut() = ScanStats::new());
+    SWITCH_STATE.lock(|state| state.set(Default::default()));
+    guard
+}
+
+/// Move the clock forward to `at_ms`, for firmware code that reads the
+/// clock itself outside [`run`].
+pub fn advance_to_ms(at_ms: u64) { CLOCK.advance_to(at_ms * 1000); }
+
+/// Read events from `device` while applying `script` to `matrix`, until
+/// `end_ms`. Returns each key event with the time it was read, in
+/// microseconds.
+pub fn run<D: InputDevice, const ROW: usize, const COL: usize>(
+    device: &mut D,
+    matrix: &MockMatrix<ROW, COL>,
+    script: &[Contact],
+    end_ms: u64,
+) -> Vec<(u64, KeyboardEvent)> {
+    let events = RefCell::new(Vec::new());
+    drive(
+        async {
+            loop {
+                let Event::Key(event) = device.read_event().await;
+                events.borrow_mut().push((CLOCK.now(), event));
+            }
+        },
+        matrix,
+        script,
+        end_ms,
+    );
+    events.into_inner()
+}
+
+/// Poll `future` while applying `script` to `matrix`, until it completes or
+/// `end_ms` is reached, whichever is first.
+pub fn drive<F: Future, const ROW: usize, const COL: usize>(
+    future: F,
+    matrix: &MockMatrix<ROW, COL>,
+    script: &[Contact],
+    end_ms: u64,
+) -> Option<F::Output> {
+    let end = end_ms * 1000;
+    let mut script = script.to_vec();
+    script.sort_by_key(|contact| contact.at_us);
+    let mut script = script.into_iter().peekable();
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut future = pin!(future);
+
+    loop {
+        let now = CLOCK.now();
+        while let Some(contact) = script.next_if(|contact| contact.at_us <= now) {
+            matrix.set(contact.row, contact.col, contact.closed);
+        }
+
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return Some(output);
+        }
+
+        let next_contact = script.peek().map(|contact| contact.at_us);
+        let next = match (CLOCK.next_deadline(), next_contact) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => match a.or(b) {
+                Some(next) => next,
+                None => break,
+            },
+        };
+        if next > end {
+            break;
+        }
+        CLOCK.advance_to(next);
+    }
+    CLOCK.advance_to(end);
+    None
+}
@@ -0,0 +1,223 @@
+//! Renders lighting frames on the host, drawing every LED as the key cap it
+//! sits under, with the physical layout from `vial.json`.
+//!
+//! Frames show the colors the renderer hands to the LED driver, before the
+//! driver's brightness scaling and gamma.
+
+use crate::{
+    led_mappings::iso_knob::{LED_COUNT, LED_POSITION, led_at_matrix},
+    lighting::{
+        bytecode::{Inputs, Program},
+        color::Rgb,
+        theme::Theme,
+    },
+};
+
+/// Keyboard definition shipped with the firmware.
+pub const VIAL_JSON: &str = include_str!("../../vial.json");
+
+const BACKGROUND: Rgb = Rgb::new(24, 24, 24);
+/// Keys without an LED.
+const UNLIT: Rgb = Rgb::new(48, 48, 48);
+
+/// Rectangle in key units.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub row: u8,
+    pub col: u8,
+    pub led: Option<usize>,
+    /// One rectangle, or two for stepped keys such as ISO Enter.
+    pub rects: Vec<Rect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub keys: Vec<Key>,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Layout {
+    /// Parse the KLE layout under `layouts.keymap` of a Vial definition.
+    pub fn from_vial_json(text: &str) -> Result<Self, String> {
+        let def = json::parse(text).map_err(|e| e.to_string())?;
+        let rows = &def["layouts"]["keymap"];
+        if !rows.is_array() {
+            return Err("no layouts.keymap array".into());
+        }
+
+        let mut keys = Vec::new();
+        let (mut width, mut height) = (0f32, 0f32);
+        let mut y = 0f32;
+        for row in rows.members() {
+            let mut x = 0f32;
+            let mut next = Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 };
+            let mut second: Option<Rect> = None;
+
+            for item in row.members() {
+                if item.is_object() {
+                    x += item["x"].as_f32().unwrap_or(0.0);
+                    y += item["y"].as_f32().unwrap_or(0.0);
+                    next.w = item["w"].as_f32().unwrap_or(next.w);
+                    next.h = item["h"].as_f32().unwrap_or(next.h);
+                    if item.has_key("w2") || item.has_key("h2") || item.has_key("x2") || item.has_key("y2") {
+                        second = Some(Rect {
+                            x: item["x2"].as_f32().unwrap_or(0.0),
+                            y: item["y2"].as_f32().unwrap_or(0.0),
+                            w: item["w2"].as_f32().unwrap_or(next.w),
+                            h: item["h2"].as_f32().unwrap_or(next.h),
+                        });
+                    }
+                    continue;
+                }
+
+                let label = item.as_str().ok_or("key label is not a string")?;
+                let matrix = label.lines().next().unwrap_or_default();
+                let (row, col) = matrix
+                    .split_once(',')
+                    .and_then(|(r, c)| Some((r.trim().parse().ok()?, c.trim().parse().ok()?)))
+                    .ok_or_else(|| format!("key label `{label}` is not `row,col`"))?;
+
+                let main = Rect { x, y, ..next };
+                let mut rects = vec![main];
+                if let Some(r) = second.take() {
+                    rects.push(Rect { x: x + r.x, y: y + r.y, ..r });
+                }
+                for r in &rects {
+                    width = width.max(r.x + r.w);
+                    height = height.max(r.y + r.h);
+                }
+                keys.push(Key { row, col, led: led_at_matrix(row, col), rects });
+
+                x += next.w;
+                next = Rect { x: 0.0, y: 0.0, w: 1.0, h: 1.0 };
+            }
+            y += 1.0;
+        }
+
+        Ok(Self { keys, width, height })
+    }
+
+    /// Layout of this board.
+    pub fn board() -> Self { Self::from_vial_json(VIAL_JSON).expect("vial.json has a valid layout") }
+}
+
+/// Colors of all LEDs for a theme at `now_ms`.
+pub fn theme_colors(theme: &Theme, now_ms: u32) -> [Rgb; LED_COUNT] {
+    std::array::from_fn(|led| theme.render(led, now_ms))
+}
+
+/// Colors of all LEDs for a custom effect program, with `hits` giving the
+/// time of the last press of each key. There is no frame budget here; LEDs
+/// whose run fails show the base color, as on the board.
+pub fn program_colors(
+    program: &Program,
+    theme: &Theme,
+    now_ms: u32,
+    hits: &[Option<u32>; LED_COUNT],
+) -> [Rgb; LED_COUNT] {
+    std::array::from_fn(|led| {
+        let inputs = Inputs {
+            now_ms,
+            pos: LED_POSITION[led],
+            hit_ms: hits[led].map_or(u32::MAX, |t| now_ms.wrapping_sub(t)),
+            color: theme.color,
+            speed: theme.speed,
+            led: led as u8,
+        };
+        let mut budget = u32::MAX;
+        program.run(&inputs, &mut budget).unwrap_or(theme.color).to_rgb()
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major pixels.
+    pub pixels: Vec<Rgb>,
+}
+
+impl Frame {
+    /// Draw every key in the color of its LED, `scale` pixels per key unit,
+    /// with a one pixel gap between caps.
+    pub fn render(layout: &Layout, scale: usize, colors: &[Rgb; LED_COUNT]) -> Self {
+        let s = scale as f32;
+        let width = (layout.width * s).ceil() as usize;
+        let height = (layout.height * s).ceil() as usize;
+        let mut pixels = vec![BACKGROUND; width * height];
+
+        for key in &layout.keys {
+            let color = key.led.map_or(UNLIT, |led| colors[led]);
+            for r in &key.rects {
+                let x0 = (r.x * s).round() as usize + 1;
+                let y0 = (r.y * s).round() as usize + 1;
+                let x1 = (((r.x + r.w) * s).round() as usize).min(width);
+                let y1 = (((r.y + r.h) * s).round() as usize).min(height);
+                for y in y0..y1.saturating_sub(1) {
+                    pixels[y * width + x0..y * width + x1.saturating_sub(1)].fill(color);
+                }
+            }
+        }
+
+        Self { width, height, pixels }
+    }
+
+    fn rgb_bytes(&self) -> Vec<u8> { self.pixels.iter().flat_map(|p| [p.r, p.g, p.b]).collect() }
+
+    /// Binary PPM (P6).
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend(self.rgb_bytes());
+        out
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().expect("in-memory PNG header");
+        writer.write_image_data(&self.rgb_bytes()).expect("in-memory PNG data");
+        writer.finish().expect("in-memory PNG end");
+        out
+    }
+
+    /// Read back an 8-bit RGB PNG, as written by [`to_png`](Self::to_png).
+    pub fn from_png(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = png::Decoder::new(bytes).read_info().map_err(|e| e.to_string())?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(|e| e.to_string())?;
+        if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+            return Err("not an 8-bit RGB PNG".into());
+        }
+
+        let pixels = buf[..info.buffer_size()].chunks_exact(3).map(|p| Rgb::new(p[0], p[1], p[2])).collect();
+        Ok(Self { width: info.width as usize, height: info.height as usize, pixels })
+    }
+
+    /// Truecolor terminal preview, two pixel rows per text line.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for y in (0..self.height).step_by(2) {
+            for x in 0..self.width {
+                let top = self.pixels[y * self.width + x];
+                let bottom = if y + 1 < self.height { self.pixels[(y + 1) * self.width + x] } else { BACKGROUND };
+                out += &format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                    top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+                );
+            }
+            out += "\x1b[0m\n";
+        }
+        out
+    }
+}
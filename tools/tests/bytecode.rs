@@ -0,0 +1,145 @@
+use q1pro_tools::{
+    effect_compiler::compile,
+    lighting::{
+        bytecode::{BUILTIN, Inputs, MAX_PROGRAM_LEN, Program, TIME_PERIOD_MS, VmError, op},
+        color::Hsv,
+    },
+};
+
+const INPUTS: Inputs =
+    Inputs { now_ms: 1000, pos: (30, 45), hit_ms: 200, color: Hsv { h: 10, s: 20, v: 30 }, speed: 128, led: 7 };
+
+fn run(source: &str, inputs: &Inputs) -> Hsv {
+    let program = Program::new(&compile(source).unwrap()).unwrap();
+    let mut budget = u32::MAX;
+    program.run(inputs, &mut budget).unwrap()
+}
+
+#[test]
+fn defaults_to_base_color() {
+    assert_eq!(run("", &INPUTS), Hsv::new(10, 20, 30));
+}
+
+#[test]
+fn reads_inputs() {
+    assert_eq!(run("h = x\ns = y\nv = hit", &INPUTS), Hsv::new(30, 45, 200));
+    assert_eq!(run("h = led\ns = speed\nv = t / 10", &INPUTS), Hsv::new(7, 128, 100));
+    assert_eq!(run("h = hue + sat + val", &INPUTS), Hsv::new(60, 20, 30));
+}
+
+#[test]
+fn never_hit_reads_as_large() {
+    let inputs = Inputs { hit_ms: u32::MAX, ..INPUTS };
+    assert_eq!(run("v = hit > 30000", &inputs).v, 1);
+}
+
+#[test]
+fn precedence_and_operators() {
+    assert_eq!(run("h = 2 + 3 * 4\ns = (2 + 3) * 4\nv = 100 - 10 - 5", &INPUTS), Hsv::new(14, 20, 85));
+    assert_eq!(run("h = 1 << 4 | 1\ns = 0xF0 & 0x3C ^ 0x01\nv = 17 % 5", &INPUTS), Hsv::new(17, 0x31, 2));
+    assert_eq!(run("h = -5 + 10\ns = !0\nv = !7", &INPUTS), Hsv::new(5, 1, 0));
+}
+
+#[test]
+fn functions() {
+    assert_eq!(run("h = min(3, 9)\ns = max(3, 9)\nv = abs(-4)", &INPUTS), Hsv::new(3, 9, 4));
+    assert_eq!(run("h = tri(64)\ns = tri(192)\nv = sqrt(3 * 3 + 4 * 4)", &INPUTS), Hsv::new(128, 126, 5));
+}
+
+#[test]
+fn conditional() {
+    let source = "v = if(hit < 500, 255, 10)";
+    assert_eq!(run(source, &INPUTS).v, 255);
+    assert_eq!(run(source, &Inputs { hit_ms: 600, ..INPUTS }).v, 10);
+    assert_eq!(run("v = if(0, 1, if(1, 2, 3))", &INPUTS).v, 2);
+}
+
+#[test]
+fn saturation_and_value_are_clamped() {
+    assert_eq!(run("s = -20\nv = 1000 * 1000 / 1000", &INPUTS), Hsv::new(10, 0, 255));
+}
+
+#[test]
+fn hue_wraps_around() {
+    assert_eq!(run("h = 300", &INPUTS).h, 44);
+    assert_eq!(run("h = 256", &INPUTS).h, 0);
+    assert_eq!(run("h = -1", &INPUTS).h, 255);
+    assert_eq!(run("h = -300", &INPUTS).h, 212);
+}
+
+#[test]
+fn time_restarts_without_a_jump_in_hue() {
+    let ripple = "h = hue + x - t * speed / 2048";
+    let at = |now_ms: u32| run(ripple, &Inputs { now_ms, speed: 255, ..INPUTS }).h;
+
+    // Days in, where `t * speed` would long have overflowed.
+    assert_eq!(run("h = t", &Inputs { now_ms: 10 * TIME_PERIOD_MS + 5, ..INPUTS }).h, 5);
+    assert_eq!(at(4000 * TIME_PERIOD_MS + 700), at(700));
+    // Across the restart the hue moves by the same step as anywhere else,
+    // give or take the rounding of the division.
+    let step = |from: u32| at(from).wrapping_sub(at(from + 1000));
+    assert!(step(TIME_PERIOD_MS - 500).abs_diff(step(500)) <= 1);
+}
+
+#[test]
+fn division_by_zero_gives_zero() {
+    assert_eq!(run("h = 5 / 0\ns = 5 % 0", &INPUTS), Hsv::new(0, 0, 30));
+}
+
+#[test]
+fn budget_is_charged_per_instruction() {
+    let program = Program::new(&[op::PUSH8, 1, op::PUSH8, 2, op::PUSH8, 3, op::OUT]).unwrap();
+    let mut budget = 10;
+    assert_eq!(program.run(&INPUTS, &mut budget), Ok(Hsv::new(1, 2, 3)));
+    assert_eq!(budget, 6);
+
+    let mut budget = 3;
+    assert_eq!(program.run(&INPUTS, &mut budget), Err(VmError::Budget));
+    assert_eq!(budget, 0);
+}
+
+#[test]
+fn frame_budget_runs_out_across_leds() {
+    let program = Program::new(&compile("h = x * 2").unwrap()).unwrap();
+    let mut budget = 20;
+    let rendered = (0..10).take_while(|_| program.run(&INPUTS, &mut budget).is_ok()).count();
+    assert_eq!(rendered, 3);
+}
+
+#[test]
+fn stack_errors() {
+    let overflow: Vec<u8> = [op::TIME; 17].into_iter().chain([op::OUT]).collect();
+    assert_eq!(Program::new(&overflow).unwrap().run(&INPUTS, &mut 100), Err(VmError::StackOverflow));
+    assert_eq!(Program::new(&[op::ADD]).unwrap().run(&INPUTS, &mut 100), Err(VmError::StackUnderflow));
+    assert_eq!(Program::new(&[op::TIME]).unwrap().run(&INPUTS, &mut 100), Err(VmError::NoOutput));
+    assert_eq!(Program::EMPTY.run(&INPUTS, &mut 100), Err(VmError::NoOutput));
+}
+
+#[test]
+fn verifier_rejects_malformed_code() {
+    assert_eq!(Program::new(&[0xEE]).err(), Some(VmError::InvalidOpcode));
+    assert_eq!(Program::new(&[op::PUSH16, 1]).err(), Some(VmError::Truncated));
+    assert_eq!(Program::new(&[op::JMP, 5, op::OUT]).err(), Some(VmError::BadJump));
+    // Lands on the operand of PUSH8.
+    assert_eq!(Program::new(&[op::JMP, 1, op::PUSH8, 1, op::OUT]).err(), Some(VmError::BadJump));
+    assert_eq!(Program::new(&[op::OUT; MAX_PROGRAM_LEN + 1]).err(), Some(VmError::TooLong));
+    // Jumping to the very end is allowed.
+    assert!(Program::new(&[op::JMP, 0]).is_ok());
+}
+
+#[test]
+fn compile_errors() {
+    assert_eq!(compile("v = foo").unwrap_err().message, "unknown input `foo`");
+    assert_eq!(compile("h = 1\nh = 2").unwrap_err().line, 2);
+    assert!(compile("v = (1 + 2").is_err());
+    assert!(compile("v = 1 2").is_err());
+    assert!(compile("q = 1").is_err());
+    assert!(compile("v = 40000").is_err());
+}
+
+#[test]
+fn builtin_program_is_the_compiled_ripple() {
+    let source = include_str!("../effects/ripple.fx");
+    assert_eq!(compile(source).unwrap(), BUILTIN);
+    assert!(Program::new(BUILTIN).is_ok());
+}
@@ -0,0 +1,184 @@
+use embassy_futures::block_on;
+use embedded_hal_async::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+use q1pro_tools::{
+    ckled2001::{
+        driver::{Ckled2001, CkledError},
+        registers::*,
+    },
+    led_mappings::iso_knob::{LED_COUNT, LED_LAYOUT},
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+const ADDRS: [u8; 2] = [0x74, 0x77];
+
+/// Register file of one driver, as far as the driver code reads it back.
+#[derive(Default)]
+struct Chip {
+    page: u8,
+    registers: HashMap<(u8, u8), u8>,
+    /// Transactions and bytes written, address bytes included.
+    transactions: usize,
+    bytes: usize,
+}
+
+/// Bus with a driver at each address it is sent to; `nak` ones do not
+/// answer. Clones share the bus, so a test can look at it while the driver
+/// owns it.
+#[derive(Clone, Default)]
+struct MockBus(Rc<RefCell<Bus>>);
+
+#[derive(Default)]
+struct Bus {
+    chips: HashMap<u8, Chip>,
+    nak: Vec<u8>,
+}
+
+impl MockBus {
+    fn nak(&self, address: u8, on: bool) {
+        let nak = &mut self.0.borrow_mut().nak;
+        nak.retain(|&a| a != address);
+        if on {
+            nak.push(address);
+        }
+    }
+
+    fn configured(&self, address: u8) -> bool {
+        let bus = self.0.borrow();
+        bus.chips.get(&address).and_then(|chip| chip.registers.get(&(FUNCTION_PAGE, CONFIGURATION_REG)))
+            == Some(&MSKSW_NORMAL_MODE)
+    }
+
+    /// Transactions and bytes sent to `address` since the last call.
+    fn take_traffic(&self, address: u8) -> (usize, usize) {
+        let mut bus = self.0.borrow_mut();
+        let chip = bus.chips.entry(address).or_default();
+        (std::mem::take(&mut chip.transactions), std::mem::take(&mut chip.bytes))
+    }
+
+    fn register(&self, address: u8, page: u8, reg: u8) -> Option<u8> {
+        self.0.borrow().chips.get(&address).and_then(|chip| chip.registers.get(&(page, reg)).copied())
+    }
+
+    /// Power-on reset of one driver.
+    fn reset(&self, address: u8) { self.0.borrow_mut().chips.remove(&address); }
+}
+
+impl ErrorType for MockBus {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockBus {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let mut bus = self.0.borrow_mut();
+        if bus.nak.contains(&address) {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        let chip = bus.chips.entry(address).or_default();
+        chip.transactions += 1;
+        chip.bytes += 1;
+        let mut reg = 0;
+        for operation in operations {
+            match operation {
+                Operation::Write([CONFIGURE_CMD_PAGE, page]) => {
+                    chip.page = *page;
+                    chip.bytes += 2;
+                }
+                Operation::Write([start, data @ ..]) => {
+                    chip.bytes += 1 + data.len();
+                    reg = *start;
+                    for (i, &value) in data.iter().enumerate() {
+                        chip.registers.insert((chip.page, start.wrapping_add(i as u8)), value);
+                    }
+                }
+                Operation::Write([]) => {}
+                Operation::Read(buf) => {
+                    for (i, byte) in buf.iter_mut().enumerate() {
+                        *byte = chip.registers.get(&(chip.page, reg.wrapping_add(i as u8))).copied().unwrap_or(0);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn backlight() -> (MockBus, Ckled2001<MockBus, 2>) {
+    let bus = MockBus::default();
+    (bus.clone(), Ckled2001::new(bus, ADDRS, &[]))
+}
+
+#[test]
+fn recover_leaves_configured_drivers_alone() {
+    let (_bus, mut driver) = backlight();
+    block_on(driver.init()).unwrap();
+    assert!(!block_on(driver.recover()).unwrap());
+}
+
+#[test]
+fn recover_restores_a_reset_driver() {
+    let (bus, mut driver) = backlight();
+    block_on(driver.init()).unwrap();
+
+    bus.reset(ADDRS[1]);
+    assert!(!bus.configured(ADDRS[1]));
+    assert!(block_on(driver.recover()).unwrap());
+    assert!(bus.configured(ADDRS[1]));
+}
+
+#[test]
+fn recover_tries_every_driver_past_a_failure() {
+    let (bus, mut driver) = backlight();
+    block_on(driver.init()).unwrap();
+    bus.reset(ADDRS[0]);
+    bus.reset(ADDRS[1]);
+
+    // Driver 0 drops off the bus; driver 1 is still restored.
+    bus.nak(ADDRS[0], true);
+    assert!(matches!(block_on(driver.recover()), Err(CkledError::I2c)));
+    assert!(bus.configured(ADDRS[1]));
+
+    // Driver 0 is restored once it answers again.
+    bus.nak(ADDRS[0], false);
+    assert!(block_on(driver.recover()).unwrap());
+    assert!(bus.configured(ADDRS[0]));
+}
+
+#[test]
+fn a_changed_page_goes_out_in_two_transactions() {
+    let bus = MockBus::default();
+    let mut driver = Ckled2001::<_, 2>::new(bus.clone(), ADDRS, LED_LAYOUT);
+    block_on(driver.init()).unwrap();
+    for address in ADDRS {
+        bus.take_traffic(address);
+    }
+
+    for led in 0..LED_COUNT {
+        driver.set_color(led, 255, 255, 255);
+    }
+    driver.present();
+    block_on(driver.flush()).unwrap();
+
+    // Page select, then register byte and the 192 PWM bytes: 197 bytes on
+    // the wire with the address bytes, per driver.
+    for address in ADDRS {
+        assert_eq!(bus.take_traffic(address), (2, 3 + 1 + 1 + LED_PWM_LENGTH), "driver {address:#x}");
+    }
+
+    // Nothing changed: nothing is written.
+    driver.present();
+    block_on(driver.flush()).unwrap();
+    assert!(ADDRS.iter().all(|&address| bus.take_traffic(address) == (0, 0)));
+}
+
+#[test]
+fn init_switches_every_led_channel_on() {
+    let (bus, mut driver) = backlight();
+    block_on(driver.init()).unwrap();
+
+    for address in ADDRS {
+        for reg in 0..LED_CONTROL_ON_OFF_LENGTH as u8 {
+            assert_eq!(bus.register(address, LED_CONTROL_PAGE, reg), Some(0xFF), "driver {address:#x} reg {reg:#x}");
+        }
+        assert_eq!(bus.register(address, LED_CONTROL_PAGE, LED_CONTROL_ON_OFF_LENGTH as u8), None);
+    }
+}
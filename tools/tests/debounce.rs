@@ -0,0 +1,84 @@
+use q1pro_tools::{
+    debounce::{EagerPress, PerRowEager, SymmetricDefer},
+    matrix_sim,
+};
+use rmk::{
+    debounce::{DebounceState, DebouncerTrait},
+    matrix::KeyState,
+};
+
+/// Feed `pattern` to key (0, 0), one reading every 2 ms, and return the
+/// times at which its debounced state flipped, with the new state. The
+/// debounce time is the default 10 ms, five readings.
+fn changes<D: DebouncerTrait>(pattern: &str) -> Vec<(u64, bool)> {
+    let _sim = matrix_sim::start();
+    let mut debouncer = D::new();
+    let mut state = KeyState::default();
+    let mut out = Vec::new();
+    for (i, c) in pattern.chars().filter(|c| !c.is_whitespace()).enumerate() {
+        let ms = i as u64 * 2;
+        matrix_sim::advance_to_ms(ms);
+        if debouncer.detect_change_with_debounce(0, 0, c == '#', &state) == DebounceState::Debounced {
+            state.pressed = !state.pressed;
+            out.push((ms, state.pressed));
+        }
+    }
+    out
+}
+
+/// Press bouncing for 3 readings, held, then a release bouncing for 3.
+const BOUNCY_TAP: &str = "__#_##_######### ####_#__________";
+
+#[test]
+fn symmetric_defer_waits_for_stable_readings() {
+    // Stable from 14 ms and from 44 ms; both reported 10 ms later.
+    assert_eq!(changes::<SymmetricDefer<2, 2>>(BOUNCY_TAP), [(24, true), (54, false)]);
+}
+
+#[test]
+fn symmetric_defer_drops_glitches() {
+    assert_eq!(changes::<SymmetricDefer<2, 2>>("__###_____##_________"), []);
+}
+
+#[test]
+fn eager_press_reports_the_first_contact() {
+    // Press at first contact; release once stable for 10 ms.
+    assert_eq!(changes::<EagerPress<2, 2>>(BOUNCY_TAP), [(4, true), (54, false)]);
+}
+
+#[test]
+fn eager_press_turns_a_glitch_into_a_tap() {
+    // The release is deferred from the end of the lockout at 14 ms.
+    assert_eq!(changes::<EagerPress<2, 2>>("__#__________"), [(4, true), (24, false)]);
+}
+
+#[test]
+fn per_row_eager_reports_both_edges_at_once() {
+    // Each edge at its first reading; bounces within the hold are ignored.
+    assert_eq!(changes::<PerRowEager<2, 2>>(BOUNCY_TAP), [(4, true), (40, false)]);
+}
+
+#[test]
+fn per_row_eager_holds_the_whole_row() {
+    let _sim = matrix_sim::start();
+    let mut debouncer = PerRowEager::<2, 2>::new();
+    let released = KeyState::default();
+
+    assert_eq!(debouncer.detect_change_with_debounce(0, 0, true, &released), DebounceState::Debounced);
+    // Same row: waits for the hold to end.
+    matrix_sim::advance_to_ms(2);
+    assert_eq!(debouncer.detect_change_with_debounce(0, 1, true, &released), DebounceState::InProgress);
+    // Other row: not held.
+    assert_eq!(debouncer.detect_change_with_debounce(1, 1, true, &released), DebounceState::Debounced);
+    matrix_sim::advance_to_ms(10);
+    assert_eq!(debouncer.detect_change_with_debounce(0, 1, true, &released), DebounceState::Debounced);
+}
+
+#[test]
+fn out_of_range_keys_are_ignored() {
+    let _sim = matrix_sim::start();
+    let mut debouncer = EagerPress::<2, 2>::new();
+    let released = KeyState::default();
+    assert_eq!(debouncer.detect_change_with_debounce(2, 0, true, &released), DebounceState::Ignored);
+    assert_eq!(debouncer.detect_change_with_debounce(0, 2, true, &released), DebounceState::Ignored);
+}
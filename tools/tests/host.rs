@@ -0,0 +1,210 @@
+use embassy_futures::{
+    block_on,
+    select::{Either3, select3},
+};
+use embassy_time::Duration;
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use q1pro_tools::{
+    debounce::Algorithm,
+    host::{
+        self,
+        CHANNEL_MATRIX,
+        CUSTOM_GET_VALUE,
+        CUSTOM_SAVE,
+        CUSTOM_SET_VALUE,
+        MATRIX_DEBOUNCE_MS,
+        MATRIX_KEY_DEBOUNCE_MS,
+        MATRIX_SETTLE_US,
+        Report,
+        UNHANDLED,
+        VIA_GET_KEYBOARD_VALUE,
+        VIA_SWITCH_MATRIX_STATE,
+    },
+    matrix_mock::MockMatrix,
+    matrix_settings::{Defaults, MatrixSettings},
+    matrix_sim::{self, Contact},
+    shiftreg_matrix::{DEBOUNCE, ScanTiming, ShiftRegMatrix},
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// Flash partition in RAM. Clones share it, so settings can be restored from
+/// what an earlier instance saved.
+#[derive(Clone)]
+struct RamFlash(Rc<RefCell<Vec<u8>>>);
+
+impl RamFlash {
+    fn new() -> Self { Self(Rc::new(RefCell::new(vec![0xFF; 2 * Self::ERASE_SIZE]))) }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let flash = self.0.borrow();
+        let data = flash.get(offset as usize..offset as usize + bytes.len()).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        bytes.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize { self.0.borrow().len() }
+}
+
+impl NorFlash for RamFlash {
+    const ERASE_SIZE: usize = 2048;
+    const WRITE_SIZE: usize = 8;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let mut flash = self.0.borrow_mut();
+        flash.get_mut(from as usize..to as usize).ok_or(NorFlashErrorKind::OutOfBounds)?.fill(0xFF);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut flash = self.0.borrow_mut();
+        let cells =
+            flash.get_mut(offset as usize..offset as usize + bytes.len()).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        // Programming only clears bits.
+        for (cell, &byte) in cells.iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
+
+fn report(command: u8, channel: u8, id: u8, data: &[u8]) -> Report {
+    let mut report = [0; host::REPORT_LENGTH];
+    report[..3].copy_from_slice(&[command, channel, id]);
+    report[host::DATA..host::DATA + data.len()].copy_from_slice(data);
+    report
+}
+
+/// Send each request through [`host::handle_report`] with the router and
+/// the matrix settings running, as the firmware runs them.
+fn exchange(settings: &mut MatrixSettings<RamFlash>, requests: &[Report]) -> Vec<Report> {
+    let client = async {
+        let mut replies = Vec::new();
+        for &request in requests {
+            replies.push(host::handle_report(request).await);
+        }
+        replies
+    };
+    match block_on(select3(host::run(), settings.run(), client)) {
+        Either3::Third(replies) => replies,
+        _ => unreachable!("the host tasks never return"),
+    }
+}
+
+#[test]
+fn debounce_time_is_set_and_read_back() {
+    let _sim = matrix_sim::start();
+    let mut settings = MatrixSettings::new(RamFlash::new(), Defaults::DEFAULT);
+
+    let set = report(CUSTOM_SET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[20]);
+    let get = report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[]);
+    let replies = exchange(&mut settings, &[set, get]);
+
+    assert_eq!(replies[0], set);
+    assert_eq!(replies[1], report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[20]));
+    assert_eq!(DEBOUNCE.try_take(), Some((Algorithm::SymmetricDefer, Duration::from_millis(20))));
+}
+
+#[test]
+fn refused_and_unknown_requests_come_back_unhandled() {
+    let _sim = matrix_sim::start();
+    let mut settings = MatrixSettings::new(RamFlash::new(), Defaults::DEFAULT);
+
+    let out_of_range = report(CUSTOM_SET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[0]);
+    let unknown_channel = report(CUSTOM_GET_VALUE, 0x55, 0x01, &[]);
+    let replies = exchange(&mut settings, &[out_of_range, unknown_channel]);
+
+    for (reply, request) in replies.iter().zip([out_of_range, unknown_channel]) {
+        assert_eq!(reply[0], UNHANDLED);
+        assert_eq!(reply[1..], request[1..]);
+    }
+    assert_eq!(DEBOUNCE.try_take(), None);
+}
+
+#[test]
+fn saved_settings_are_restored() {
+    let _sim = matrix_sim::start();
+    let flash = RamFlash::new();
+    let mut settings = MatrixSettings::new(flash.clone(), Defaults::DEFAULT);
+
+    let set = report(CUSTOM_SET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[12]);
+    let save = report(CUSTOM_SAVE, CHANNEL_MATRIX, 0, &[]);
+    let replies = exchange(&mut settings, &[set, save]);
+    assert_eq!(replies, [set, save]);
+
+    let mut restored = MatrixSettings::new(flash, Defaults::DEFAULT);
+    block_on(restored.restore());
+    assert_eq!(restored.debounce(), (Algorithm::SymmetricDefer, Duration::from_millis(12)));
+}
+
+#[test]
+fn build_time_defaults_hold_until_settings_are_saved() {
+    let _sim = matrix_sim::start();
+    let flash = RamFlash::new();
+    let defaults = Defaults {
+        timing: ScanTiming::from_micros(60, 400),
+        algorithm: Algorithm::EagerPress,
+        debounce_ms: 7,
+        key_ms: [[0; 16]; 6],
+        mask_stuck: true,
+    };
+
+    let mut settings = MatrixSettings::new(flash.clone(), defaults);
+    block_on(settings.restore());
+    assert!(settings.timing() == defaults.timing);
+    assert_eq!(settings.debounce(), (Algorithm::EagerPress, Duration::from_millis(7)));
+    assert!(settings.mask_stuck());
+    let get = report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_SETTLE_US, &[]);
+    assert_eq!(exchange(&mut settings, &[get]), [report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_SETTLE_US, &[60, 0])]);
+
+    let set = report(CUSTOM_SET_VALUE, CHANNEL_MATRIX, MATRIX_DEBOUNCE_MS, &[12]);
+    let save = report(CUSTOM_SAVE, CHANNEL_MATRIX, 0, &[]);
+    exchange(&mut settings, &[set, save]);
+    let mut restored = MatrixSettings::new(flash, defaults);
+    block_on(restored.restore());
+    assert_eq!(restored.debounce(), (Algorithm::EagerPress, Duration::from_millis(12)));
+}
+
+#[test]
+fn build_time_key_times_are_checked_and_served() {
+    let _sim = matrix_sim::start();
+    let mut key_ms = [[0; 16]; 6];
+    key_ms[2][3] = 25;
+    key_ms[4][4] = 200;
+    let mut settings = MatrixSettings::new(RamFlash::new(), Defaults { key_ms, ..Defaults::DEFAULT });
+    block_on(settings.restore());
+    assert_eq!((settings.key_debounce()[2][3], settings.key_debounce()[4][4]), (25, 0));
+
+    let get = report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_KEY_DEBOUNCE_MS, &[2, 3]);
+    assert_eq!(
+        exchange(&mut settings, &[get]),
+        [report(CUSTOM_GET_VALUE, CHANNEL_MATRIX, MATRIX_KEY_DEBOUNCE_MS, &[2, 3, 25])]
+    );
+}
+
+#[test]
+fn switch_matrix_state_is_answered_from_the_scanner() {
+    let _sim = matrix_sim::start();
+    let board = MockMatrix::<6, 16>::new();
+    let mut matrix: ShiftRegMatrix<_, _, 6, 16> = ShiftRegMatrix::builder(board.rows(), board.cols()).build();
+    let script = [Contact::press(10, 2, 3), Contact::press(10, 5, 15)];
+    matrix_sim::run(&mut matrix, &board, &script, 50);
+
+    // Vial's query is `[0x02, 0x03]`; the rows follow in its place, two
+    // bytes each, most significant first.
+    let query = report(VIA_GET_KEYBOARD_VALUE, VIA_SWITCH_MATRIX_STATE, 0, &[]);
+    let mut expected = [0; host::REPORT_LENGTH];
+    expected[..2].copy_from_slice(&[VIA_GET_KEYBOARD_VALUE, VIA_SWITCH_MATRIX_STATE]);
+    expected[2 + 2 * 2 + 1] = 1 << 3;
+    expected[2 + 5 * 2] = 1 << 7;
+
+    let mut settings = MatrixSettings::new(RamFlash::new(), Defaults::DEFAULT);
+    assert_eq!(exchange(&mut settings, &[query]), [expected]);
+}
@@ -0,0 +1,79 @@
+use embassy_time::{Duration, Instant};
+use q1pro_tools::key_health::{CHATTER_GAP, FLAG_CHATTER, FLAG_MASKED, FLAG_STUCK, KeyHealth, Offender, STUCK_AFTER};
+
+fn at(ms: u64) -> Instant { Instant::from_millis(ms) }
+
+fn offenders<const R: usize, const C: usize>(health: &KeyHealth<R, C>) -> Vec<Offender> { health.offenders().collect() }
+
+#[test]
+fn fast_repeated_presses_count_as_chatter() {
+    let mut health = KeyHealth::<2, 2>::new();
+    let gap = CHATTER_GAP.as_millis();
+
+    // A human double tap, then two presses too close together.
+    for (ms, pressed) in [(0, true), (30, false), (gap, true), (gap + 10, false), (gap + 20, true)] {
+        assert!(health.changed(0, 1, pressed, at(ms)));
+    }
+    assert_eq!(offenders(&health), [Offender { row: 0, col: 1, flags: FLAG_CHATTER, chatters: 1 }]);
+
+    health.clear_chatter();
+    assert_eq!(offenders(&health), []);
+}
+
+#[test]
+fn long_holds_are_flagged_until_released() {
+    let mut health = KeyHealth::<2, 2>::new();
+    let stuck_ms = STUCK_AFTER.as_millis();
+    health.changed(1, 0, true, at(0));
+
+    health.check_stuck(at(stuck_ms - 1), |_, _| panic!("masking is off"));
+    assert_eq!(offenders(&health), []);
+    health.check_stuck(at(stuck_ms), |_, _| panic!("masking is off"));
+    assert_eq!(offenders(&health), [Offender { row: 1, col: 0, flags: FLAG_STUCK, chatters: 0 }]);
+
+    assert!(health.changed(1, 0, false, at(stuck_ms + 5)));
+    assert_eq!(offenders(&health), []);
+}
+
+#[test]
+fn masked_keys_are_released_once() {
+    let mut health = KeyHealth::<2, 2>::new();
+    health.set_mask_stuck(true);
+    health.changed(0, 0, true, at(0));
+    let later = at(0) + STUCK_AFTER;
+
+    let mut released = Vec::new();
+    health.check_stuck(later, |r, c| {
+        released.push((r, c));
+        true
+    });
+    health.check_stuck(later + Duration::from_secs(1), |r, c| {
+        released.push((r, c));
+        true
+    });
+    assert_eq!(released, [(0, 0)]);
+    assert_eq!(offenders(&health), [Offender { row: 0, col: 0, flags: FLAG_STUCK | FLAG_MASKED, chatters: 0 }]);
+
+    // The real release was already reported; the next press is reported again.
+    assert!(!health.changed(0, 0, false, later + Duration::from_secs(2)));
+    assert!(health.changed(0, 0, true, later + Duration::from_secs(3)));
+    assert_eq!(offenders(&health), []);
+}
+
+#[test]
+fn masking_retries_when_the_release_was_not_queued() {
+    let mut health = KeyHealth::<1, 1>::new();
+    health.set_mask_stuck(true);
+    health.changed(0, 0, true, at(0));
+    let later = at(0) + STUCK_AFTER;
+
+    health.check_stuck(later, |_, _| false);
+    assert_eq!(offenders(&health)[0].flags, FLAG_STUCK);
+    let mut calls = 0;
+    health.check_stuck(later, |_, _| {
+        calls += 1;
+        true
+    });
+    assert_eq!(calls, 1);
+    assert_eq!(offenders(&health)[0].flags, FLAG_STUCK | FLAG_MASKED);
+}
@@ -0,0 +1,119 @@
+use q1pro_tools::{
+    led_mappings::iso_knob::{LED_COUNT, LED_POSITION, led_at_matrix},
+    lighting::{
+        color::{Hsv, Rgb},
+        effect::Effect,
+        knob::{Knob, KnobReaction},
+        theme::{LightingConfig, Theme},
+    },
+};
+
+/// A reaction stays up this long after the last detent, fading out over
+/// the last 500 ms.
+const SHOW_MS: u32 = 1500;
+
+fn frame(knob: &Knob, reaction: KnobReaction, now_ms: u32, base: Rgb) -> Vec<Rgb> {
+    (0..LED_COUNT).map(|led| knob.render(reaction, led, now_ms, base)).collect()
+}
+
+#[test]
+fn nothing_is_drawn_before_the_first_turn_or_after_the_reaction() {
+    let mut knob = Knob::default();
+    let base = Rgb::new(10, 20, 30);
+    for reaction in [KnobReaction::Sweep, KnobReaction::LevelBar] {
+        assert!(frame(&knob, reaction, 0, base).iter().all(|&c| c == base));
+    }
+
+    knob.turn(KnobReaction::LevelBar, true, 1000);
+    assert!(frame(&knob, KnobReaction::LevelBar, 1000, base).iter().any(|&c| c != base));
+    assert!(frame(&knob, KnobReaction::LevelBar, 1000 + SHOW_MS, base).iter().all(|&c| c == base));
+    assert!(frame(&knob, KnobReaction::None, 1000, base).iter().all(|&c| c == base));
+}
+
+#[test]
+fn the_level_bar_follows_the_detents_on_the_number_row() {
+    let mut knob = Knob::default();
+    let bar = |knob: &Knob| -> Vec<u8> {
+        (0..14)
+            .map(|col| knob.render(KnobReaction::LevelBar, led_at_matrix(1, col).unwrap(), 0, Rgb::BLACK).r)
+            .collect()
+    };
+
+    // Half full at boot: seven keys lit.
+    knob.turn(KnobReaction::LevelBar, true, 0);
+    knob.turn(KnobReaction::LevelBar, false, 0);
+    assert_eq!(bar(&knob), [[255; 7].as_slice(), &[0; 7]].concat());
+
+    // One detent up lights half a key.
+    knob.turn(KnobReaction::LevelBar, true, 0);
+    assert_eq!(bar(&knob)[6..8], [255, 64]);
+
+    // The bar stops at both ends.
+    for _ in 0..40 {
+        knob.turn(KnobReaction::LevelBar, true, 0);
+    }
+    assert_eq!(bar(&knob), [255; 14]);
+    for _ in 0..40 {
+        knob.turn(KnobReaction::LevelBar, false, 0);
+    }
+    assert_eq!(bar(&knob), [0; 14]);
+}
+
+#[test]
+fn the_sweep_moves_with_the_turning_direction() {
+    let mut knob = Knob::default();
+    let brightest = |knob: &Knob| -> u8 {
+        let frame = frame(knob, KnobReaction::Sweep, 0, Rgb::BLACK);
+        let led = (0..LED_COUNT).max_by_key(|&led| frame[led].r).unwrap();
+        LED_POSITION[led].0
+    };
+
+    knob.turn(KnobReaction::Sweep, true, 0);
+    let start = brightest(&knob);
+    knob.turn(KnobReaction::Sweep, true, 0);
+    knob.turn(KnobReaction::Sweep, true, 0);
+    let right = brightest(&knob);
+    assert!(right > start, "{right} <= {start}");
+    for _ in 0..4 {
+        knob.turn(KnobReaction::Sweep, false, 0);
+    }
+    assert!(brightest(&knob) < start);
+}
+
+#[test]
+fn hue_steps_shift_the_render_and_not_the_theme() {
+    let mut knob = Knob::default();
+    knob.turn(KnobReaction::HueStep, true, 0);
+    knob.turn(KnobReaction::HueStep, true, 0);
+    assert_eq!(knob.hue_shift(KnobReaction::HueStep), 16);
+    // Only shown under HueStep, and other reactions do not turn it.
+    assert_eq!(knob.hue_shift(KnobReaction::Sweep), 0);
+    knob.turn(KnobReaction::Sweep, true, 0);
+    assert_eq!(knob.hue_shift(KnobReaction::HueStep), 16);
+
+    for _ in 0..3 {
+        knob.turn(KnobReaction::HueStep, false, 0);
+    }
+    assert_eq!(knob.hue_shift(KnobReaction::HueStep), 248);
+
+    let theme = Theme::new(Effect::Solid, Hsv::new(0, 255, 255), 128);
+    let shifted = Theme::new(Effect::Solid, Hsv::new(248, 255, 255), 128);
+    assert_eq!(theme.render_shifted(3, 0, knob.hue_shift(KnobReaction::HueStep)), shifted.render(3, 0));
+    assert_eq!(theme.color, Hsv::new(0, 255, 255));
+    // HueStep draws nothing over the effect itself.
+    assert!(frame(&knob, KnobReaction::HueStep, 0, Rgb::WHITE).iter().all(|&c| c == Rgb::WHITE));
+}
+
+#[test]
+fn every_effect_has_a_reaction_by_default() {
+    let config = LightingConfig::default();
+    assert!(config.knob(Effect::Off) == KnobReaction::None);
+    assert!(config.knob(Effect::Solid) == KnobReaction::LevelBar);
+    assert!(config.knob(Effect::Breathing) == KnobReaction::HueStep);
+    assert!(config.knob(Effect::Pinwheel) == KnobReaction::Sweep);
+    let unset = (0..Effect::COUNT as u8)
+        .filter_map(Effect::from_u8)
+        .filter(|&effect| effect != Effect::Off && config.knob(effect) == KnobReaction::None)
+        .count();
+    assert_eq!(unset, 0);
+}
@@ -0,0 +1,60 @@
+use embassy_futures::{block_on, poll_once};
+use q1pro_tools::{
+    matrix_io::{ColumnDriver, RowReader},
+    matrix_mock::{ColumnFault, MockMatrix},
+};
+
+#[test]
+fn rows_show_keys_of_active_columns_only() {
+    let matrix = MockMatrix::<2, 3>::new();
+    let (mut cols, rows) = (matrix.cols(), matrix.rows());
+    matrix.press(1, 2);
+
+    block_on(cols.select(2)).unwrap();
+    assert!(rows.is_pressed(1));
+    assert!(!rows.is_pressed(0));
+
+    block_on(cols.select(0)).unwrap();
+    assert!(!rows.is_pressed(1));
+
+    block_on(cols.select_all()).unwrap();
+    assert!(rows.is_pressed(1));
+
+    block_on(cols.unselect_all()).unwrap();
+    assert!(!rows.is_pressed(1));
+    assert_eq!(matrix.selects(), 4);
+}
+
+#[test]
+fn wait_for_any_needs_an_active_column() {
+    let matrix = MockMatrix::<2, 3>::new();
+    let (mut cols, mut rows) = (matrix.cols(), matrix.rows());
+
+    block_on(cols.select_all()).unwrap();
+    {
+        let mut wait = std::pin::pin!(rows.wait_for_any());
+        assert!(poll_once(wait.as_mut()).is_pending());
+        matrix.press(0, 1);
+        assert!(poll_once(wait.as_mut()).is_ready());
+    }
+
+    // Already pressed: returns straight away.
+    block_on(rows.wait_for_any());
+}
+
+#[test]
+fn faulty_column_writes_fail_and_change_nothing() {
+    let matrix = MockMatrix::<2, 3>::new();
+    let (mut cols, rows) = (matrix.cols(), matrix.rows());
+    matrix.press(0, 1);
+    matrix.set_faulty(Some(1));
+
+    assert_eq!(block_on(cols.select(1)), Err(ColumnFault));
+    assert!(!rows.is_pressed(0));
+    assert_eq!(block_on(cols.select_all()), Err(ColumnFault));
+    block_on(cols.select(2)).unwrap();
+
+    matrix.set_faulty(None);
+    block_on(cols.select(1)).unwrap();
+    assert!(rows.is_pressed(0));
+}
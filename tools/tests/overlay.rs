@@ -0,0 +1,75 @@
+use q1pro_tools::{
+    led_mappings::iso_knob::LED_COUNT,
+    lighting::{
+        color::{Hsv, Rgb},
+        effect::Effect,
+        overlay::{MASK_LEN, NOTIFICATION_LEN, Notification},
+        theme::Theme,
+    },
+};
+
+const RED: Hsv = Hsv { h: 0, s: 255, v: 255 };
+const SOLID: u8 = 0;
+const BLINK: u8 = 1;
+
+fn notification(color: Hsv, pattern: u8, tenths: u16, leds: &[usize], now_ms: u32) -> Option<Notification> {
+    let mut data = [0u8; NOTIFICATION_LEN];
+    data[..4].copy_from_slice(&[color.h, color.s, color.v, pattern]);
+    data[4..6].copy_from_slice(&tenths.to_le_bytes());
+    for &led in leds {
+        data[6 + led / 8] |= 1 << (led % 8);
+    }
+    Notification::decode(&data, now_ms)
+}
+
+/// The frame the controller draws: the notification over a theme.
+fn frame(notification: &Notification, theme: &Theme, now_ms: u32) -> Vec<Rgb> {
+    (0..LED_COUNT).map(|led| notification.over(led, now_ms, theme.render(led, now_ms))).collect()
+}
+
+#[test]
+fn masked_leds_show_the_notification_and_the_rest_the_theme() {
+    let theme = Theme::new(Effect::Solid, Hsv::new(85, 255, 255), 128);
+    let flash = notification(RED, SOLID, 10, &[0, 9, LED_COUNT - 1], 1000).unwrap();
+
+    for (led, color) in frame(&flash, &theme, 1500).into_iter().enumerate() {
+        let expected = if [0, 9, LED_COUNT - 1].contains(&led) { RED.to_rgb() } else { theme.render(led, 1500) };
+        assert_eq!(color, expected, "LED {led}");
+    }
+}
+
+#[test]
+fn an_empty_mask_covers_the_whole_board() {
+    let theme = Theme::new(Effect::Solid, Hsv::WHITE, 128);
+    let flash = notification(RED, SOLID, 10, &[], 0).unwrap();
+    assert!(frame(&flash, &theme, 0).iter().all(|&color| color == RED.to_rgb()));
+    assert_eq!(MASK_LEN, LED_COUNT.div_ceil(8));
+}
+
+#[test]
+fn the_theme_comes_back_when_the_notification_expires() {
+    let theme = Theme::new(Effect::Breathing, Hsv::new(170, 255, 255), 128);
+    let flash = notification(RED, SOLID, 20, &[], 1000).unwrap();
+
+    assert!(!flash.expired(2999));
+    assert_eq!(flash.over(3, 2999, Rgb::BLACK), RED.to_rgb());
+    assert!(flash.expired(3000));
+    assert_eq!(frame(&flash, &theme, 3000), (0..LED_COUNT).map(|led| theme.render(led, 3000)).collect::<Vec<_>>());
+
+    // A zero duration has already expired, which clears a running one.
+    assert!(notification(RED, SOLID, 0, &[], 1000).unwrap().expired(1000));
+}
+
+#[test]
+fn blink_alternates_with_black() {
+    let flash = notification(RED, BLINK, 10, &[], 0).unwrap();
+    let base = Rgb::WHITE;
+    assert_eq!(flash.over(0, 100, base), RED.to_rgb());
+    assert_eq!(flash.over(0, 300, base), Rgb::BLACK);
+    assert_eq!(flash.over(0, 600, base), RED.to_rgb());
+}
+
+#[test]
+fn unknown_patterns_are_refused() {
+    assert!(notification(RED, 3, 10, &[], 0).is_none());
+}
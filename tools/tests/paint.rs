@@ -0,0 +1,72 @@
+use q1pro_tools::{
+    led_mappings::iso_knob::{LED_COUNT, led_at_matrix},
+    lighting::{
+        color::Hsv,
+        paint::{KNOB_PRESS, PaintMode},
+        setup::Exit,
+    },
+};
+
+const BASE: Hsv = Hsv { h: 0, s: 255, v: 255 };
+const ESC: (u8, u8) = (0, 0);
+const BACKSPACE: (u8, u8) = (1, 13);
+
+/// Matrix position of every LED.
+fn led_keys() -> Vec<(u8, u8)> {
+    (0..LED_COUNT)
+        .map(|led| {
+            (0..6u8)
+                .flat_map(|row| (0..16u8).map(move |col| (row, col)))
+                .find(|&(row, col)| led_at_matrix(row, col) == Some(led))
+                .unwrap_or_else(|| panic!("LED {led} has no key"))
+        })
+        .collect()
+}
+
+#[test]
+fn knob_press_toggles_only_with_a_key_selected() {
+    let mut paint = PaintMode::new([BASE; LED_COUNT]);
+    let knob_led = led_at_matrix(KNOB_PRESS.0, KNOB_PRESS.1).unwrap();
+
+    // Nothing selected: the knob press selects the knob's LED.
+    assert_eq!(paint.press(KNOB_PRESS.0, KNOB_PRESS.1), None);
+    paint.turn(true);
+    assert_eq!(paint.keys()[knob_led].h, 8);
+
+    // Selected: the knob press switches to saturation.
+    assert_eq!(paint.press(KNOB_PRESS.0, KNOB_PRESS.1), None);
+    paint.turn(false);
+    assert_eq!(paint.keys()[knob_led], Hsv::new(8, 247, 255));
+}
+
+#[test]
+fn every_led_can_be_painted() {
+    for (led, (row, col)) in led_keys().into_iter().enumerate() {
+        let mut paint = PaintMode::new([BASE; LED_COUNT]);
+        // Esc and Backspace finish when nothing is selected, so reach them
+        // from another key; the knob press only selects its LED from nothing.
+        if (row, col) == ESC || (row, col) == BACKSPACE {
+            assert_eq!(paint.press(1, 1), None);
+        }
+        assert_eq!(paint.press(row, col), None, "LED {led} finished painting");
+        paint.turn(true);
+
+        let changed: Vec<usize> = (0..LED_COUNT).filter(|&i| paint.keys()[i] != BASE).collect();
+        assert_eq!(changed, [led], "painting LED {led}");
+    }
+}
+
+#[test]
+fn esc_keeps_and_backspace_discards_only_with_nothing_selected() {
+    let mut paint = PaintMode::new([BASE; LED_COUNT]);
+    assert_eq!(paint.press(ESC.0, ESC.1), Some(Exit::Save));
+    assert_eq!(paint.press(BACKSPACE.0, BACKSPACE.1), Some(Exit::Discard));
+
+    // With a key selected, both select their own LED instead, and a second
+    // press deselects it.
+    assert_eq!(paint.press(2, 2), None);
+    assert_eq!(paint.press(ESC.0, ESC.1), None);
+    assert_eq!(paint.press(BACKSPACE.0, BACKSPACE.1), None);
+    assert_eq!(paint.press(BACKSPACE.0, BACKSPACE.1), None);
+    assert_eq!(paint.press(BACKSPACE.0, BACKSPACE.1), Some(Exit::Discard));
+}
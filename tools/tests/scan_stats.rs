@@ -0,0 +1,68 @@
+use embassy_time::Instant;
+use q1pro_tools::scan_stats::{ENCODED_LEN, ScanStats};
+
+fn at(us: u64) -> Instant { Instant::from_micros(us) }
+
+fn encoded(stats: &ScanStats) -> [u8; ENCODED_LEN] {
+    let mut out = [0; ENCODED_LEN];
+    stats.encode(&mut out);
+    out
+}
+
+fn u16_at(out: &[u8], i: usize) -> u16 { u16::from_le_bytes([out[i], out[i + 1]]) }
+
+fn u32_at(out: &[u8], i: usize) -> u32 { u32::from_le_bytes([out[i], out[i + 1], out[i + 2], out[i + 3]]) }
+
+#[test]
+fn passes_give_rate_average_and_worst_case() {
+    let mut stats = ScanStats::new();
+    // 500 us passes with 500 us pauses, one slower pass in the middle. The
+    // pass starting after a whole second closes the window.
+    for i in 0..=1000u64 {
+        let start = at(i * 1000);
+        let len = if i == 500 { 900 } else { 500 };
+        stats.record_pass(start, at(i * 1000 + len));
+        stats.record_wait(false);
+    }
+
+    let out = encoded(&stats);
+    assert_eq!(u16_at(&out, 0), 1000);
+    assert_eq!(u16_at(&out, 2), 500);
+    assert_eq!(u16_at(&out, 4), 900);
+    assert_eq!(u32_at(&out, 10), 0);
+    assert_eq!(u32_at(&out, 14), 1001);
+}
+
+#[test]
+fn sleeping_restarts_the_rate_window() {
+    let mut stats = ScanStats::new();
+    stats.record_pass(at(0), at(500));
+    stats.record_wait(true);
+    // Without the restart, this pass would close a window holding 2 passes.
+    stats.record_pass(at(5_000_000), at(5_000_500));
+
+    let out = encoded(&stats);
+    assert_eq!(u16_at(&out, 0), 0);
+    assert_eq!(u32_at(&out, 10), 1);
+}
+
+#[test]
+fn post_time_is_averaged_and_saturates() {
+    let mut stats = ScanStats::new();
+    stats.record_post(at(0), at(100));
+    stats.record_post(at(0), at(300));
+    let out = encoded(&stats);
+    assert_eq!(u16_at(&out, 6), 200);
+    assert_eq!(u16_at(&out, 8), 300);
+
+    stats.record_post(at(0), at(1_000_000));
+    assert_eq!(u16_at(&encoded(&stats), 8), u16::MAX);
+}
+
+#[test]
+fn column_errors_are_counted() {
+    let mut stats = ScanStats::new();
+    stats.record_column_error();
+    stats.record_column_error();
+    assert_eq!(u32_at(&encoded(&stats), 18), 2);
+}
@@ -0,0 +1,136 @@
+use q1pro_tools::lighting::{
+    effect::Effect,
+    knob::KnobReaction,
+    setup::{Exit, Field, SetupMode},
+    theme::{LightingConfig, MAX_IDLE_MINUTES, MAX_TRANSITION},
+};
+
+const KNOB_PRESS: (u8, u8) = (0, 15);
+
+#[test]
+fn the_knob_reaches_every_effect_and_wraps() {
+    let mut config = LightingConfig::default();
+    let setup = SetupMode::new(config);
+    assert_eq!(setup.field(), Field::Effect);
+
+    let start = config.themes[0].effect as usize;
+    let mut seen = vec![start];
+    for _ in 0..Effect::COUNT {
+        setup.turn(&mut config, 0, true);
+        seen.push(config.themes[0].effect as usize);
+    }
+    assert_eq!(seen.last(), Some(&start));
+    seen.sort_unstable();
+    seen.dedup();
+    assert_eq!(seen, (0..Effect::COUNT).collect::<Vec<_>>());
+
+    setup.turn(&mut config, 0, false);
+    assert_eq!(config.themes[0].effect as usize, (start + Effect::COUNT - 1) % Effect::COUNT);
+}
+
+#[test]
+fn number_keys_and_the_knob_press_pick_the_setting() {
+    let mut setup = SetupMode::new(LightingConfig::default());
+
+    assert_eq!(setup.press(1, 8), None);
+    assert_eq!(setup.field(), Field::Idle);
+    assert_eq!(setup.press(KNOB_PRESS.0, KNOB_PRESS.1), None);
+    assert_eq!(setup.field(), Field::Effect);
+    assert_eq!(setup.press(KNOB_PRESS.0, KNOB_PRESS.1), None);
+    assert_eq!(setup.field(), Field::Hue);
+    assert_eq!(setup.press(1, 5), None);
+    assert_eq!(setup.field(), Field::Brightness);
+    assert_eq!(setup.press(1, 2), None);
+    assert_eq!(setup.field(), Field::Hue);
+
+    // Other keys change nothing.
+    assert_eq!(setup.press(3, 4), None);
+    assert_eq!(setup.field(), Field::Hue);
+}
+
+#[test]
+fn values_stay_in_range() {
+    let mut config = LightingConfig::default();
+    let mut setup = SetupMode::new(config);
+
+    setup.press(1, 5);
+    for _ in 0..20 {
+        setup.turn(&mut config, 0, true);
+    }
+    assert_eq!(config.brightness, 100);
+    for _ in 0..20 {
+        setup.turn(&mut config, 0, false);
+    }
+    assert_eq!(config.brightness, 0);
+
+    setup.press(1, 3);
+    for _ in 0..20 {
+        setup.turn(&mut config, 0, true);
+    }
+    assert_eq!(config.themes[0].color.s, 255);
+
+    setup.press(1, 2);
+    let hue = config.themes[0].color.h;
+    setup.turn(&mut config, 0, false);
+    assert_eq!(config.themes[0].color.h, hue.wrapping_sub(8));
+}
+
+#[test]
+fn esc_saves_and_backspace_discards() {
+    let mut config = LightingConfig::default();
+    let mut setup = SetupMode::new(config);
+    setup.turn(&mut config, 0, true);
+    assert!(config.themes[0].effect != setup.saved().themes[0].effect);
+
+    assert_eq!(setup.press(1, 13), Some(Exit::Discard));
+    assert!(setup.saved().themes[0].effect == LightingConfig::default().themes[0].effect);
+    assert_eq!(setup.press(0, 0), Some(Exit::Save));
+}
+
+#[test]
+fn the_knob_reaction_is_set_for_the_current_effect() {
+    let mut config = LightingConfig::default();
+    let mut setup = SetupMode::new(config);
+    assert_eq!(setup.press(1, 6), None);
+    assert_eq!(setup.field(), Field::Knob);
+
+    let effect = config.themes[0].effect;
+    let before = config.knob(effect) as u8;
+    setup.turn(&mut config, 0, true);
+    assert_eq!(config.knob(effect) as u8, (before + 1) % KnobReaction::COUNT as u8);
+    setup.turn(&mut config, 0, false);
+    assert_eq!(config.knob(effect) as u8, before);
+    for _ in 0..KnobReaction::COUNT {
+        setup.turn(&mut config, 0, true);
+    }
+    assert_eq!(config.knob(effect) as u8, before);
+    // Other effects keep theirs.
+    assert!(config.knob[Effect::Radial as usize] == LightingConfig::default().knob[Effect::Radial as usize]);
+}
+
+#[test]
+fn fade_timings_are_set_on_keys_7_and_8() {
+    let mut config = LightingConfig::default();
+    let mut setup = SetupMode::new(config);
+
+    setup.press(1, 7);
+    assert_eq!(setup.field(), Field::Transition);
+    let before = config.transition;
+    setup.turn(&mut config, 0, true);
+    assert_eq!(config.transition, before + 5);
+    for _ in 0..100 {
+        setup.turn(&mut config, 0, true);
+    }
+    assert_eq!(config.transition, MAX_TRANSITION);
+
+    setup.press(1, 8);
+    assert_eq!(setup.field(), Field::Idle);
+    for _ in 0..100 {
+        setup.turn(&mut config, 0, true);
+    }
+    assert_eq!(config.idle_minutes, MAX_IDLE_MINUTES);
+    for _ in 0..100 {
+        setup.turn(&mut config, 0, false);
+    }
+    assert_eq!(config.idle_minutes, 0);
+}
@@ -0,0 +1,224 @@
+use embassy_time::{Duration, Timer};
+use q1pro_tools::{
+    debounce::Algorithm,
+    matrix_mock::{MockCols, MockMatrix, MockRows},
+    matrix_sim::{self, Contact},
+    scan_stats::ENCODED_LEN,
+    shiftreg_matrix::{DEBOUNCE, SCAN_STATS, ShiftRegMatrix},
+};
+use rmk::event::{Event, KeyboardEvent};
+
+type Matrix = ShiftRegMatrix<MockRows<6, 16>, MockCols<6, 16>, 6, 16>;
+
+/// Board scanner with the default timing: passes of 16 x 30 us, 100 us
+/// apart while keys are held.
+fn matrix(algorithm: Algorithm) -> (MockMatrix<6, 16>, Matrix) {
+    let board = MockMatrix::new();
+    let matrix =
+        ShiftRegMatrix::builder(board.rows(), board.cols()).debounce(algorithm, Duration::from_millis(5)).build();
+    (board, matrix)
+}
+
+fn key(row: u8, col: u8, pressed: bool) -> KeyboardEvent { KeyboardEvent::key(row, col, pressed) }
+
+fn events(run: &[(u64, KeyboardEvent)]) -> Vec<KeyboardEvent> { run.iter().map(|&(_, event)| event).collect() }
+
+#[test]
+fn clean_tap_is_reported_after_the_debounce_time() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    let script = [Contact::press(10, 2, 3), Contact::release(60, 2, 3)];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 200);
+    assert_eq!(events(&run), [key(2, 3, true), key(2, 3, false)]);
+    // A pass starts every 580 us: up to one to see the change, one more to
+    // see it settled, and the rest of that pass before it is read.
+    assert!((15_000..16_700).contains(&run[0].0), "press at {} us", run[0].0);
+    assert!((65_000..66_700).contains(&run[1].0), "release at {} us", run[1].0);
+}
+
+#[test]
+fn bounce_gives_one_press_and_one_release() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    let mut script = Contact::bounce(10, 0, 0, 4, true);
+    script.extend(Contact::bounce(80, 0, 0, 3, false));
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 200);
+    assert_eq!(events(&run), [key(0, 0, true), key(0, 0, false)]);
+}
+
+#[test]
+fn glitches_never_get_through_symmetric_defer() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    let script = [Contact::press(10, 1, 1), Contact::release(12, 1, 1)];
+
+    assert_eq!(matrix_sim::run(&mut matrix, &board, &script, 100), []);
+}
+
+#[test]
+fn eager_press_reports_on_the_first_closed_reading() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::EagerPress);
+    let mut script = Contact::bounce(10, 4, 9, 2, true);
+    script.push(Contact::release(50, 4, 9));
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    assert_eq!(events(&run), [key(4, 9, true), key(4, 9, false)]);
+    // On the first reading that catches the contact closed: at the latest
+    // one pass after the bounce ends at 10.8 ms, long before a deferred
+    // press could be.
+    assert!(run[0].0 < 12_000, "press at {} us", run[0].0);
+    assert!(run[1].0 >= 55_000, "release at {} us", run[1].0);
+}
+
+#[test]
+fn chord_comes_out_in_scan_order() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    // Pressed in the opposite order, within one pass.
+    let script = [
+        Contact { at_us: 10_000, ..Contact::press(0, 5, 12) },
+        Contact { at_us: 10_050, ..Contact::press(0, 3, 4) },
+        Contact { at_us: 10_100, ..Contact::press(0, 1, 4) },
+        Contact::release(40, 1, 4),
+        Contact::release(40, 3, 4),
+        Contact::release(40, 5, 12),
+    ];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    assert_eq!(
+        events(&run),
+        [key(1, 4, true), key(3, 4, true), key(5, 12, true), key(1, 4, false), key(3, 4, false), key(5, 12, false)]
+    );
+}
+
+#[test]
+fn changes_past_a_full_queue_wait_for_the_next_pass() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    // 18 keys at once, more than one pass can queue.
+    let keys: Vec<(usize, usize)> = (0..6).flat_map(|row| (0..3).map(move |col| (row, col))).collect();
+    let script: Vec<Contact> = keys.iter().map(|&(row, col)| Contact::press(10, row, col)).collect();
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    let mut expected: Vec<KeyboardEvent> = keys.iter().map(|&(r, c)| key(r as u8, c as u8, true)).collect();
+    expected.sort_by_key(|event| match event.pos {
+        rmk::event::KeyboardEventPos::Key(pos) => (pos.col, pos.row),
+    });
+    assert_eq!(events(&run), expected);
+
+    // The first 16 come out of one pass; the rest are found on the next.
+    assert!(run[..16].iter().all(|&(at, _)| at == run[0].0));
+    assert!(run[16].0 > run[15].0);
+}
+
+#[test]
+fn masked_stuck_key_is_released_once() {
+    let _sim = matrix_sim::start();
+    let board = MockMatrix::<6, 16>::new();
+    // Long idle time, so a 30 s hold takes fewer passes to simulate.
+    let mut matrix: Matrix =
+        ShiftRegMatrix::builder(board.rows(), board.cols()).idle(Duration::from_millis(5)).mask_stuck(true).build();
+    let script = [Contact::press(10, 2, 2), Contact::release(31_000, 2, 2), Contact::press(31_100, 2, 2)];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 31_200);
+    assert_eq!(events(&run), [key(2, 2, true), key(2, 2, false), key(2, 2, true)]);
+    assert!((30_020_000..30_030_000).contains(&run[1].0), "masked at {} us", run[1].0);
+}
+
+#[test]
+fn column_that_cannot_be_selected_is_skipped_and_counted() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+    board.set_faulty(Some(7));
+    // Only column 7 fails: its key never shows, the one next to it does.
+    let script = [Contact::press(10, 1, 7), Contact::press(10, 1, 8), Contact::release(40, 1, 8)];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    assert_eq!(events(&run), [key(1, 8, true), key(1, 8, false)]);
+
+    let mut out = [0; ENCODED_LEN];
+    SCAN_STATS.lock(|stats| stats.borrow().encode(&mut out));
+    let column_errors = u32::from_le_bytes(out[18..22].try_into().unwrap());
+    assert!(column_errors > 0);
+}
+
+#[test]
+fn switching_algorithm_forgets_pending_changes() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::SymmetricDefer);
+
+    assert_eq!(matrix_sim::run(&mut matrix, &board, &[Contact::press(10, 0, 0)], 13), []);
+    // The press seen since 10 ms starts over under the new time.
+    DEBOUNCE.signal((Algorithm::SymmetricDefer, Duration::from_millis(10)));
+    let run = matrix_sim::run(&mut matrix, &board, &[], 60);
+    assert_eq!(events(&run), [key(0, 0, true)]);
+    assert!(run[0].0 >= 23_000, "press at {} us", run[0].0);
+}
+
+#[test]
+fn key_time_overrides_the_default_for_that_key_only() {
+    let _sim = matrix_sim::start();
+    let board = MockMatrix::new();
+    let mut key_ms = [[0; 16]; 6];
+    key_ms[0][0] = 20;
+    let mut matrix: Matrix = ShiftRegMatrix::builder(board.rows(), board.cols())
+        .debounce(Algorithm::SymmetricDefer, Duration::from_millis(5))
+        .key_debounce(&key_ms)
+        .build();
+    let script = [Contact::press(10, 0, 0), Contact::press(10, 0, 1)];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    assert_eq!(events(&run), [key(0, 1, true), key(0, 0, true)]);
+    assert!((15_000..16_700).contains(&run[0].0), "default key at {} us", run[0].0);
+    assert!((30_000..31_700).contains(&run[1].0), "slow key at {} us", run[1].0);
+}
+
+#[test]
+fn key_times_survive_algorithm_changes() {
+    let _sim = matrix_sim::start();
+    let board = MockMatrix::new();
+    let mut key_ms = [[0; 16]; 6];
+    key_ms[1][1] = 20;
+    let mut matrix: Matrix = ShiftRegMatrix::builder(board.rows(), board.cols()).key_debounce(&key_ms).build();
+    DEBOUNCE.signal((Algorithm::PerRowEager, Duration::from_millis(5)));
+    let script = [Contact::press(10, 1, 1), Contact::press(12, 1, 0)];
+
+    let run = matrix_sim::run(&mut matrix, &board, &script, 100);
+    assert_eq!(events(&run), [key(1, 1, true), key(1, 0, true)]);
+    // The row is held for the changed key's own time.
+    assert!(run[0].0 < 11_000, "first key at {} us", run[0].0);
+    assert!((30_000..31_700).contains(&run[1].0), "second key at {} us", run[1].0);
+}
+
+#[test]
+fn latency_is_counted_until_the_event_is_posted() {
+    let _sim = matrix_sim::start();
+    let (board, mut matrix) = matrix(Algorithm::EagerPress);
+    let script = [Contact::press(10, 0, 0), Contact::release(40, 0, 0), Contact::press(80, 0, 1)];
+    let mut posted = Vec::new();
+
+    // Posting takes 2 ms, and the second key goes elsewhere.
+    matrix_sim::drive(
+        matrix.post_events(async |Event::Key(event)| {
+            if event == key(0, 1, true) {
+                return false;
+            }
+            Timer::after_millis(2).await;
+            posted.push(event);
+            true
+        }),
+        &board,
+        &script,
+        150,
+    );
+    assert_eq!(posted, [key(0, 0, true), key(0, 0, false)]);
+
+    let mut out = [0; ENCODED_LEN];
+    SCAN_STATS.lock(|stats| stats.borrow().encode(&mut out));
+    let (avg, max) = (u16::from_le_bytes([out[6], out[7]]), u16::from_le_bytes([out[8], out[9]]));
+    assert!((2000..2600).contains(&avg), "average {avg} us");
+    assert!(max >= avg && max < 2600, "worst {max} us");
+}
@@ -0,0 +1,106 @@
+//! Golden images of every built-in effect. Regenerate after an intended change
+//! with `UPDATE_GOLDEN=1 cargo make host-test` and review the PNGs.
+
+use q1pro_tools::{
+    effect_compiler::compile,
+    led_mappings::iso_knob::{LED_COUNT, led_at_matrix},
+    lighting::{bytecode::Program, color::Hsv, effect::Effect, theme::Theme},
+    simulator::{Frame, Layout, program_colors, theme_colors},
+};
+use std::{env, fs, path::PathBuf};
+
+const SCALE: usize = 8;
+const NOW_MS: u32 = 1500;
+
+fn check_golden(name: &str, frame: &Frame) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{name}.png"));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, frame.to_png()).unwrap();
+        return;
+    }
+
+    let golden = fs::read(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    if Frame::from_png(&golden).unwrap() != *frame {
+        let actual = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.png"));
+        fs::write(&actual, frame.to_png()).unwrap();
+        panic!("{name} differs from {}, see {}", path.display(), actual.display());
+    }
+}
+
+fn check_effect(name: &str, effect: Effect) {
+    let theme = Theme::new(effect, Hsv::new(170, 255, 255), 128);
+    check_golden(name, &Frame::render(&Layout::board(), SCALE, &theme_colors(&theme, NOW_MS)));
+}
+
+#[test]
+fn layout_matches_led_mapping() {
+    let layout = Layout::board();
+    let mut leds: Vec<usize> = layout.keys.iter().filter_map(|k| k.led).collect();
+    leds.sort_unstable();
+    assert_eq!(leds, (0..LED_COUNT).collect::<Vec<_>>());
+    assert_eq!((layout.width, layout.height), (16.25, 6.5));
+}
+
+#[test]
+fn iso_enter_has_two_parts() {
+    let layout = Layout::board();
+    let enter = layout.keys.iter().find(|k| (k.row, k.col) == (2, 13)).unwrap();
+    assert_eq!(enter.rects.len(), 2);
+    assert_eq!(enter.led, led_at_matrix(2, 13));
+}
+
+#[test]
+fn png_round_trip() {
+    let theme = Theme::new(Effect::CycleAll, Hsv::new(0, 255, 255), 200);
+    let frame = Frame::render(&Layout::board(), 4, &theme_colors(&theme, 777));
+    assert_eq!(Frame::from_png(&frame.to_png()).unwrap(), frame);
+}
+
+#[test]
+fn effect_off() { check_effect("off", Effect::Off) }
+
+#[test]
+fn effect_solid() { check_effect("solid", Effect::Solid) }
+
+#[test]
+fn effect_breathing() { check_effect("breathing", Effect::Breathing) }
+
+#[test]
+fn effect_cycle_all() { check_effect("cycle_all", Effect::CycleAll) }
+
+#[test]
+fn effect_per_key() {
+    let mut theme = Theme::new(Effect::PerKey, Hsv::new(0, 0, 80), 128);
+    for (i, key) in theme.keys.iter_mut().enumerate() {
+        *key = Hsv::new((i * 3) as u8, 255, 255);
+    }
+    check_golden("per_key", &Frame::render(&Layout::board(), SCALE, &theme_colors(&theme, NOW_MS)));
+}
+
+#[test]
+fn effect_wave_horizontal() { check_effect("wave_horizontal", Effect::WaveHorizontal) }
+
+#[test]
+fn effect_wave_vertical() { check_effect("wave_vertical", Effect::WaveVertical) }
+
+#[test]
+fn effect_radial() { check_effect("radial", Effect::Radial) }
+
+#[test]
+fn effect_spiral() { check_effect("spiral", Effect::Spiral) }
+
+#[test]
+fn effect_pinwheel() { check_effect("pinwheel", Effect::Pinwheel) }
+
+#[test]
+fn effect_custom() {
+    let source = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/effects/ripple.fx")).unwrap();
+    let program = Program::new(&compile(&source).unwrap()).unwrap();
+    let theme = Theme::new(Effect::Custom, Hsv::new(0, 255, 255), 128);
+    let mut hits = [None; LED_COUNT];
+    hits[led_at_matrix(3, 4).unwrap()] = Some(NOW_MS - 100);
+    check_golden(
+        "custom_ripple",
+        &Frame::render(&Layout::board(), SCALE, &program_colors(&program, &theme, NOW_MS, &hits)),
+    );
+}
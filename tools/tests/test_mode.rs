@@ -0,0 +1,55 @@
+use q1pro_tools::{
+    led_mappings::iso_knob::{LED_COUNT, led_at_matrix},
+    lighting::test_mode::TestMode,
+};
+
+/// Start of the one-by-one walk, after a second each of red, green, blue
+/// and white.
+const WALK_MS: u32 = 4000;
+const WALK_STEP_MS: u32 = 150;
+
+#[test]
+fn the_walk_follows_the_layout_after_the_colors() {
+    let test = TestMode::new(0);
+    assert_eq!(test.current_led(0), None);
+    assert_eq!(test.status(WALK_MS - 1), [1, 0xFF, 0xFF, 0xFF]);
+
+    for led in [0, 1, 5, LED_COUNT - 1] {
+        assert_eq!(test.current_led(WALK_MS + led as u32 * WALK_STEP_MS), Some(led));
+    }
+    // Then the cycle starts over.
+    assert_eq!(test.current_led(WALK_MS + LED_COUNT as u32 * WALK_STEP_MS), None);
+}
+
+#[test]
+fn steps_go_one_led_at_a_time_and_stay() {
+    let mut test = TestMode::new(0);
+
+    // From the color cycle, the first step lights the first LED.
+    test.step(true, 100);
+    assert_eq!(test.current_led(100), Some(0));
+    test.step(false, 200);
+    assert_eq!(test.current_led(200), Some(LED_COUNT - 1));
+    test.step(true, 300);
+    test.step(true, 400);
+    assert_eq!(test.current_led(400), Some(1));
+    // The automatic walk no longer moves it.
+    assert_eq!(test.current_led(WALK_MS + 10 * WALK_STEP_MS), Some(1));
+
+    // From the walk, steps continue where it was.
+    let mut test = TestMode::new(0);
+    let now = WALK_MS + 7 * WALK_STEP_MS;
+    test.step(true, now);
+    assert_eq!(test.current_led(now), Some(8));
+}
+
+#[test]
+fn status_reports_the_led_and_its_matrix_position() {
+    let mut test = TestMode::new(0);
+    for led in 0..LED_COUNT {
+        test.step(true, 0);
+        let [active, index, row, col] = test.status(0);
+        assert_eq!((active, index as usize), (1, led));
+        assert_eq!(led_at_matrix(row, col), Some(led), "LED {led} at ({row}, {col})");
+    }
+}
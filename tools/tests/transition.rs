@@ -0,0 +1,53 @@
+use q1pro_tools::{
+    led_mappings::iso_knob::LED_COUNT,
+    lighting::{
+        color::Rgb,
+        theme::{LightingConfig, MAX_IDLE_MINUTES, MAX_TRANSITION},
+        transition::Transition,
+    },
+};
+
+#[test]
+fn the_crossfade_moves_from_the_old_frame_to_the_new() {
+    let from = [Rgb::new(200, 0, 0); LED_COUNT];
+    let to = Rgb::new(0, 0, 200);
+    let transition = Transition::start(&from, 1000, 300).unwrap();
+
+    assert_eq!(transition.blend(0, 1000, to), from[0]);
+    let half = transition.blend(0, 1150, to);
+    assert!((90..=110).contains(&half.r) && (90..=110).contains(&half.b), "{half:?}");
+    assert!(!transition.done(1299));
+    assert!(transition.done(1300));
+    assert_eq!(transition.blend(0, 1300, to), to);
+    // Past the end it stays on the new frame.
+    assert_eq!(transition.blend(0, 5000, to), to);
+}
+
+#[test]
+fn a_zero_duration_switches_at_once() {
+    assert!(Transition::start(&[Rgb::WHITE; LED_COUNT], 0, 0).is_none());
+}
+
+#[test]
+fn the_idle_fade_darkens_steadily_to_black() {
+    let config = LightingConfig::default();
+    let transition = Transition::start(&[Rgb::WHITE; LED_COUNT], 0, config.transition_ms()).unwrap();
+
+    let mut last = u8::MAX;
+    for now in (0..=config.transition_ms()).step_by(10) {
+        let c = transition.blend(LED_COUNT - 1, now, Rgb::BLACK);
+        assert!(c.r <= last, "brighter at {now} ms");
+        last = c.r;
+    }
+    assert_eq!(last, 0);
+}
+
+#[test]
+fn saved_timings_are_clamped() {
+    let mut bytes = [0u8; LightingConfig::ENCODED_LEN];
+    LightingConfig::default().encode(&mut bytes);
+    bytes[1] = 255;
+    bytes[2] = 255;
+    let config = LightingConfig::decode(&bytes).unwrap();
+    assert_eq!((config.transition, config.idle_minutes), (MAX_TRANSITION, MAX_IDLE_MINUTES));
+}